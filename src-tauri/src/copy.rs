@@ -0,0 +1,370 @@
+use crate::{entry_path::EntryPath, get_file_content_hash, ErrorInfo};
+use filetime::FileTime;
+use std::ffi::OsString;
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::Path;
+use walkdir::WalkDir;
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CopyOptions {
+    /// Re-hash every written file and report it as an error if it differs from the source
+    pub verify: bool,
+    /// Keep the access and modification time of the source
+    pub preserve_timestamps: bool,
+    /// Keep user and group of the source. Silently skipped if we are not allowed to change them.
+    pub preserve_ownership: bool,
+    /// Keep the extended attributes of the source. Attributes we are not allowed to set are skipped,
+    /// as are all attributes if the target doesn't support them.
+    pub preserve_xattrs: bool,
+}
+
+fn error_info(path: &Path, message: String) -> ErrorInfo {
+    ErrorInfo {
+        path: EntryPath::from(path),
+        message,
+    }
+}
+
+fn is_permission_denied(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::PermissionDenied || err.raw_os_error() == Some(1)
+    // EPERM is not mapped to PermissionDenied on every platform
+}
+
+#[cfg(unix)]
+fn set_owner(target: &Path, (uid, gid): (u32, u32)) -> io::Result<()> {
+    match std::os::unix::fs::chown(target, Some(uid), Some(gid)) {
+        Err(err) if is_permission_denied(&err) => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(not(unix))]
+fn set_owner(_target: &Path, _owner: (u32, u32)) -> io::Result<()> {
+    Ok(())
+}
+
+/// The file system of the target doesn't support extended attributes at all (like vfat)
+#[cfg(unix)]
+fn is_unsupported(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(code) if code == libc::ENOTSUP || code == libc::EOPNOTSUPP)
+}
+
+#[cfg(unix)]
+fn read_xattrs(source: &Path) -> io::Result<Vec<(OsString, Vec<u8>)>> {
+    let mut xattrs = Vec::new();
+    for name in xattr::list(source)? {
+        if let Some(value) = xattr::get(source, &name)? {
+            xattrs.push((name, value));
+        }
+    }
+    Ok(xattrs)
+}
+
+#[cfg(not(unix))]
+fn read_xattrs(_source: &Path) -> io::Result<Vec<(OsString, Vec<u8>)>> {
+    Ok(Vec::new())
+}
+
+#[cfg(unix)]
+fn set_xattrs(target: &Path, xattrs: &[(OsString, Vec<u8>)]) -> io::Result<()> {
+    for (name, value) in xattrs {
+        match xattr::set(target, name, value) {
+            Err(err) if is_permission_denied(&err) || is_unsupported(&err) => {}
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_xattrs(_target: &Path, _xattrs: &[(OsString, Vec<u8>)]) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn get_mode(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn get_mode(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn get_owner(metadata: &Metadata) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.uid(), metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn get_owner(_metadata: &Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+/// Everything `apply_metadata()` can copy from one file to another. Can be stored to restore the
+/// metadata of a file which doesn't exist anymore.
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedMetadata {
+    /// Unix permission bits, `None` on other platforms
+    mode: Option<u32>,
+    readonly: bool,
+    /// User and group id, `None` on other platforms than Unix
+    owner: Option<(u32, u32)>,
+    accessed: (i64, u32), // seconds since UNIX_EPOCH and nanoseconds
+    modified: (i64, u32), // seconds since UNIX_EPOCH and nanoseconds
+    /// Extended attributes, only read if `CopyOptions::preserve_xattrs` is set
+    xattrs: Vec<(OsString, Vec<u8>)>,
+}
+
+impl SavedMetadata {
+    pub fn read(path: &Path, metadata: &Metadata, options: &CopyOptions) -> io::Result<Self> {
+        let accessed = FileTime::from_last_access_time(metadata);
+        let modified = FileTime::from_last_modification_time(metadata);
+        Ok(SavedMetadata {
+            mode: get_mode(metadata),
+            readonly: metadata.permissions().readonly(),
+            owner: get_owner(metadata),
+            accessed: (accessed.unix_seconds(), accessed.nanoseconds()),
+            modified: (modified.unix_seconds(), modified.nanoseconds()),
+            xattrs: if options.preserve_xattrs {
+                read_xattrs(path)?
+            } else {
+                Vec::new()
+            },
+        })
+    }
+
+    #[cfg(unix)]
+    fn permissions(&self, target: &Path) -> io::Result<fs::Permissions> {
+        use std::os::unix::fs::PermissionsExt;
+        match self.mode {
+            Some(mode) => Ok(fs::Permissions::from_mode(mode)),
+            None => self.readonly_permissions(target),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn permissions(&self, target: &Path) -> io::Result<fs::Permissions> {
+        self.readonly_permissions(target)
+    }
+
+    fn readonly_permissions(&self, target: &Path) -> io::Result<fs::Permissions> {
+        let mut permissions = fs::metadata(target)?.permissions();
+        permissions.set_readonly(self.readonly);
+        Ok(permissions)
+    }
+}
+
+/// Apply `metadata` to `target` according to `options`.
+///
+/// The permissions are always copied, like `fs::copy()` does. Ownership is changed before the
+/// permissions as changing the owner may clear the setuid / setgid bits.
+pub fn apply_metadata(
+    metadata: &SavedMetadata,
+    target: &Path,
+    options: &CopyOptions,
+) -> io::Result<()> {
+    if options.preserve_ownership {
+        if let Some(owner) = metadata.owner {
+            set_owner(target, owner)?;
+        }
+    }
+    if options.preserve_xattrs {
+        set_xattrs(target, &metadata.xattrs)?;
+    }
+    fs::set_permissions(target, metadata.permissions(target)?)?;
+    if options.preserve_timestamps {
+        let (accessed_seconds, accessed_nanoseconds) = metadata.accessed;
+        let (modified_seconds, modified_nanoseconds) = metadata.modified;
+        filetime::set_file_times(
+            target,
+            FileTime::from_unix_time(accessed_seconds, accessed_nanoseconds),
+            FileTime::from_unix_time(modified_seconds, modified_nanoseconds),
+        )?;
+    }
+    Ok(())
+}
+
+/// Copy a single file without ever leaving a partially written file at `target`.
+///
+/// The content is written to a temporary file next to the target, flushed to disk and then
+/// renamed into place. A rename within one directory is atomic, so the target either keeps its
+/// old content or gets the complete new content.
+pub fn copy_file_atomic(source: &Path, target: &Path, options: &CopyOptions) -> io::Result<()> {
+    let target_dir = target.parent().unwrap_or_else(|| Path::new("."));
+    let mut temp_file = tempfile::Builder::new()
+        .prefix(".bumblebee-")
+        .suffix(".tmp")
+        .tempfile_in(target_dir)?;
+
+    let mut source_file = File::open(source)?;
+    io::copy(&mut source_file, temp_file.as_file_mut())?;
+    temp_file.as_file().sync_all()?;
+    let metadata = SavedMetadata::read(source, &source_file.metadata()?, options)?;
+    apply_metadata(&metadata, temp_file.path(), options)?;
+
+    temp_file.persist(target).map_err(|err| err.error)?;
+
+    // Make sure the rename itself survives a crash. Opening a directory is not possible on
+    // every platform, so this is best effort.
+    if let Ok(dir) = File::open(target_dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+fn verify_copy(source: &Path, target: &Path) -> Result<(), ErrorInfo> {
+    let hash_source = get_file_content_hash(source)
+        .map_err(|why| error_info(source, format!("Could not verify copy: {}", why)))?;
+    let hash_target = get_file_content_hash(target)
+        .map_err(|why| error_info(target, format!("Could not verify copy: {}", why)))?;
+    if hash_source != hash_target {
+        return Err(error_info(
+            target,
+            format!(
+                "Verification failed: content hash {} does not match source hash {}",
+                hash_target, hash_source
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn copy_file(source: &Path, target: &Path, options: &CopyOptions) -> Result<(), ErrorInfo> {
+    copy_file_atomic(source, target, options).map_err(|why| error_info(target, why.to_string()))?;
+    if options.verify {
+        verify_copy(source, target)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(link_target: &Path, _source: &Path, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(link_target, path)
+}
+
+#[cfg(windows)]
+fn create_symlink(link_target: &Path, source: &Path, path: &Path) -> io::Result<()> {
+    // Windows needs to know whether the link points to a directory
+    if source.is_dir() {
+        std::os::windows::fs::symlink_dir(link_target, path)
+    } else {
+        std::os::windows::fs::symlink_file(link_target, path)
+    }
+}
+
+/// Copy the symbolic link `source` as a link with the same target, the target itself is not
+/// touched. Like `copy_file_atomic()` the link is created next to `target` and renamed into place.
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    let link_target = fs::read_link(source)?;
+    let target_dir = target.parent().unwrap_or_else(|| Path::new("."));
+    // Only reserves a name, the temporary path is still removed if something fails
+    let temp_path = tempfile::Builder::new()
+        .prefix(".bumblebee-")
+        .suffix(".tmp")
+        .tempfile_in(target_dir)?
+        .into_temp_path();
+    fs::remove_file(&temp_path)?;
+    create_symlink(&link_target, source, &temp_path)?;
+    temp_path.persist(target).map_err(|err| err.error)?;
+    Ok(())
+}
+
+/// Copy `sub_path` from `source_path` to the same location below `target_path`.
+///
+/// Directories are copied recursively, existing files are overwritten. Symbolic links are copied
+/// as links, without their metadata. Errors are collected per file so a single unreadable file
+/// doesn't stop the remaining ones from being copied.
+pub fn copy_entry(
+    source_path: &Path,
+    target_path: &Path,
+    sub_path: &EntryPath,
+    options: &CopyOptions,
+) -> Vec<ErrorInfo> {
+    copy_entry_to(
+        &source_path.join(sub_path),
+        &target_path.join(sub_path),
+        options,
+    )
+}
+
+/// Copy the file or directory `source` to `target`, see `copy_entry()`.
+pub fn copy_entry_to(source: &Path, target: &Path, options: &CopyOptions) -> Vec<ErrorInfo> {
+    // Check the source first, so nothing is created for a missing one
+    let file_type = match fs::symlink_metadata(source) {
+        Ok(metadata) => metadata.file_type(),
+        Err(why) => return vec![error_info(source, why.to_string())],
+    };
+
+    if let Some(parent) = target.parent() {
+        if let Err(why) = fs::create_dir_all(parent) {
+            return vec![error_info(parent, why.to_string())];
+        }
+    }
+
+    if file_type.is_symlink() {
+        return copy_symlink(source, target)
+            .err()
+            .map(|why| error_info(target, why.to_string()))
+            .into_iter()
+            .collect();
+    }
+    if file_type.is_file() {
+        return copy_file(source, target, options)
+            .err()
+            .into_iter()
+            .collect();
+    }
+
+    let mut errors = Vec::new();
+    let mut copied_directories = Vec::new();
+    for result in WalkDir::new(source) {
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(why) => {
+                errors.push(error_info(why.path().unwrap_or(source), why.to_string()));
+                continue;
+            }
+        };
+        let entry_target = target.join(
+            entry
+                .path()
+                .strip_prefix(source)
+                // This should never panic as the path should always start with the base directory
+                .expect("Path doesn't start with base directory"),
+        );
+
+        if entry.file_type().is_dir() {
+            match fs::create_dir_all(&entry_target) {
+                Ok(()) => copied_directories.push((entry, entry_target)),
+                Err(why) => errors.push(error_info(&entry_target, why.to_string())),
+            }
+        } else if entry.file_type().is_symlink() {
+            if let Err(why) = copy_symlink(entry.path(), &entry_target) {
+                errors.push(error_info(&entry_target, why.to_string()));
+            }
+        } else if let Err(error) = copy_file(entry.path(), &entry_target, options) {
+            errors.push(error);
+        }
+    }
+
+    // Copying the content of a directory changes its modification time, so the metadata of the
+    // directories is applied afterwards, starting with the innermost ones.
+    for (entry, entry_target) in copied_directories.into_iter().rev() {
+        if let Err(why) = entry
+            .metadata()
+            .map_err(io::Error::from)
+            .and_then(|metadata| SavedMetadata::read(entry.path(), &metadata, options))
+            .and_then(|metadata| apply_metadata(&metadata, &entry_target, options))
+        {
+            errors.push(error_info(&entry_target, why.to_string()));
+        }
+    }
+
+    errors
+}
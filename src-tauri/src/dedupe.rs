@@ -0,0 +1,440 @@
+use crate::copy::{apply_metadata, copy_file_atomic, CopyOptions, SavedMetadata};
+use crate::duplicates::file_id;
+use crate::entry_path::EntryPath;
+use crate::{app_file, get_file_content_hash, ErrorInfo};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+const JOURNAL_FILE_NAME: &str = "dedupe-journal.json";
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum DedupeMethod {
+    /// Both paths point to the same file afterwards. Changing one changes the other.
+    Hardlink,
+    /// The file shares its data blocks with the original until one of them is changed.
+    /// Falls back to `Copy` if the filesystem doesn't support it.
+    Reflink,
+    /// A plain copy, doesn't save any space
+    Copy,
+}
+
+/// Files which should be replaced by links to `original`
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DedupeRequest {
+    original: EntryPath,
+    duplicates: Vec<EntryPath>,
+    /// The content hash all files had when the duplicates were found
+    hash: String,
+}
+
+/// A duplicate which was replaced, with everything needed to undo it
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Replacement {
+    path: EntryPath,
+    original: EntryPath,
+    method: DedupeMethod,
+    hash: String,
+    size: u64,
+    replaced_at: u64, // seconds since UNIX_EPOCH
+    /// Metadata of the replaced file, restored by `undo_replacement()`
+    metadata: SavedMetadata,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DedupeResult {
+    replacements: Vec<Replacement>,
+    errors: Vec<ErrorInfo>,
+}
+
+fn error_info(path: &Path, message: String) -> ErrorInfo {
+    ErrorInfo {
+        path: EntryPath::from(path),
+        message,
+    }
+}
+
+/// Keep everything about a replaced file except its content
+fn keep_metadata() -> CopyOptions {
+    CopyOptions {
+        preserve_timestamps: true,
+        preserve_ownership: true,
+        preserve_xattrs: true,
+        ..Default::default()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(source: &File, target: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    const FICLONE: libc::c_ulong = 0x40049409;
+    let result = unsafe { libc::ioctl(target.as_raw_fd(), FICLONE as _, source.as_raw_fd()) };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &File, _target: &File) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Reflinks are not supported on this platform",
+    ))
+}
+
+/// Replace `duplicate` by a hardlink, reflink or copy of `original`. Like `copy_file_atomic()` the
+/// new file is created next to `duplicate` and renamed into place, so `duplicate` is never lost.
+fn replace_file(
+    original: &Path,
+    duplicate: &Path,
+    metadata: &SavedMetadata,
+    method: DedupeMethod,
+) -> io::Result<DedupeMethod> {
+    let dir = duplicate.parent().unwrap_or_else(|| Path::new("."));
+    let temp_file = tempfile::Builder::new()
+        .prefix(".bumblebee-")
+        .suffix(".tmp")
+        .tempfile_in(dir)?;
+
+    if method == DedupeMethod::Hardlink {
+        // The temporary path is still removed if something fails before the rename
+        let temp_path = temp_file.into_temp_path();
+        fs::remove_file(&temp_path)?;
+        fs::hard_link(original, &temp_path)?;
+        temp_path.persist(duplicate).map_err(|err| err.error)?;
+        return Ok(method);
+    }
+
+    let mut source = File::open(original)?;
+    let mut used_method = method;
+    if method == DedupeMethod::Reflink && reflink(&source, temp_file.as_file()).is_err() {
+        used_method = DedupeMethod::Copy;
+    }
+    if used_method == DedupeMethod::Copy {
+        io::copy(&mut source, &mut temp_file.as_file())?;
+    }
+    temp_file.as_file().sync_all()?;
+    apply_metadata(metadata, temp_file.path(), &keep_metadata())?;
+    temp_file.persist(duplicate).map_err(|err| err.error)?;
+    Ok(used_method)
+}
+
+/// Check that `duplicate` can be replaced and collect what is needed to undo it. Nothing is
+/// changed yet, see `replace_file()`.
+fn prepare_replacement(
+    original: &Path,
+    duplicate: &Path,
+    expected_hash: &str,
+    method: DedupeMethod,
+) -> Result<Replacement, ErrorInfo> {
+    let original_metadata =
+        fs::metadata(original).map_err(|err| error_info(original, err.to_string()))?;
+    let metadata = fs::metadata(duplicate).map_err(|err| error_info(duplicate, err.to_string()))?;
+    if file_id(&metadata).is_some() && file_id(&metadata) == file_id(&original_metadata) {
+        return Err(error_info(
+            duplicate,
+            "Already linked to the original".to_string(),
+        ));
+    }
+
+    // The files may have changed since the duplicates were found
+    for path in [original, duplicate] {
+        let hash = get_file_content_hash(path).map_err(|err| error_info(path, err.to_string()))?;
+        if hash != expected_hash {
+            return Err(error_info(
+                path,
+                "Content changed since the duplicates were found, not replaced".to_string(),
+            ));
+        }
+    }
+
+    let saved_metadata = SavedMetadata::read(duplicate, &metadata, &keep_metadata())
+        .map_err(|err| error_info(duplicate, err.to_string()))?;
+
+    Ok(Replacement {
+        path: EntryPath::from(duplicate),
+        original: EntryPath::from(original),
+        method,
+        hash: expected_hash.to_string(),
+        size: metadata.len(),
+        replaced_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Clock may have gone backwards")
+            .as_secs(),
+        metadata: saved_metadata,
+    })
+}
+
+/// Replace all duplicates. Every replacement is appended to the journal before the file is
+/// replaced, so it can be undone even if the process dies in the middle of it. Undoing a
+/// replacement which didn't happen is harmless. Entries of replacements which failed are removed
+/// from the journal again.
+pub fn dedupe_files(
+    requests: &[DedupeRequest],
+    method: DedupeMethod,
+    journal: &Path,
+) -> Result<DedupeResult, String> {
+    // Don't replace anything which couldn't be recorded
+    let mut entries = read_journal(journal).map_err(|err| err.to_string())?;
+    let mut result = DedupeResult {
+        replacements: Vec::new(),
+        errors: Vec::new(),
+    };
+    for request in requests {
+        for duplicate in &request.duplicates {
+            let original = request.original.as_path();
+            let duplicate = duplicate.as_path();
+            let mut replacement =
+                match prepare_replacement(original, duplicate, &request.hash, method) {
+                    Ok(replacement) => replacement,
+                    Err(err) => {
+                        result.errors.push(err);
+                        continue;
+                    }
+                };
+
+            entries.push(replacement.clone());
+            write_journal(journal, &entries).map_err(|err| {
+                format!(
+                    "Could not record replacement of {}: {}",
+                    replacement.path, err
+                )
+            })?;
+
+            match replace_file(original, duplicate, &replacement.metadata, method) {
+                Ok(used_method) => {
+                    if used_method != method {
+                        replacement.method = used_method;
+                        *entries.last_mut().unwrap() = replacement.clone();
+                        write_journal(journal, &entries).map_err(|err| err.to_string())?;
+                    }
+                    result.replacements.push(replacement);
+                }
+                Err(err) => {
+                    entries.pop();
+                    write_journal(journal, &entries).map_err(|err| err.to_string())?;
+                    result.errors.push(error_info(duplicate, err.to_string()));
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Turn the replaced path back into an independent file with its old metadata
+fn undo_replacement(replacement: &Replacement) -> Result<(), ErrorInfo> {
+    let path = replacement.path.as_path();
+    let hash = get_file_content_hash(path).map_err(|err| error_info(path, err.to_string()))?;
+    if hash != replacement.hash {
+        return Err(error_info(
+            path,
+            "Content changed since it was replaced, not restored".to_string(),
+        ));
+    }
+
+    // Copying the file onto itself creates a new file which doesn't share anything with the original
+    copy_file_atomic(path, path, &CopyOptions::default())
+        .and_then(|_| apply_metadata(&replacement.metadata, path, &keep_metadata()))
+        .map_err(|err| error_info(path, err.to_string()))
+}
+
+/// Undo the replacements and remove each one from the journal right after it was undone
+pub fn undo_files(replacements: &[Replacement], journal: &Path) -> Result<Vec<ErrorInfo>, String> {
+    let mut entries = read_journal(journal).map_err(|err| err.to_string())?;
+    let mut errors = Vec::new();
+    for replacement in replacements {
+        match undo_replacement(replacement) {
+            Ok(()) => {
+                entries.retain(|entry| entry != replacement);
+                write_journal(journal, &entries).map_err(|err| err.to_string())?;
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+    Ok(errors)
+}
+
+fn read_journal(file: &Path) -> Result<Vec<Replacement>, io::Error> {
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+    let reader = BufReader::new(File::open(file)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Like `copy_file_atomic()` the journal is written to a temporary file which is renamed into
+/// place, so a crash never leaves a truncated journal behind.
+fn write_journal(file: &Path, entries: &[Replacement]) -> Result<(), io::Error> {
+    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+    let temp_file = tempfile::Builder::new()
+        .prefix(".bumblebee-")
+        .suffix(".tmp")
+        .tempfile_in(dir)?;
+    let mut writer = BufWriter::new(temp_file.as_file());
+    serde_json::to_writer(&mut writer, entries)?;
+    writer.flush()?;
+    drop(writer);
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(file).map_err(|err| err.error)?;
+    Ok(())
+}
+
+/// Replace the duplicates of every request by links to its original.
+///
+/// The content of all files is checked against the hash of the request right before replacing.
+/// Every replacement is recorded, see `list_replacements()` and `undo_replacements()`.
+#[tauri::command(async)]
+pub fn dedupe(
+    app_handle: tauri::AppHandle,
+    requests: Vec<DedupeRequest>,
+    method: DedupeMethod,
+) -> Result<DedupeResult, String> {
+    dedupe_files(
+        &requests,
+        method,
+        &app_file(&app_handle, JOURNAL_FILE_NAME)?,
+    )
+}
+
+#[tauri::command]
+pub fn list_replacements(app_handle: tauri::AppHandle) -> Result<Vec<Replacement>, String> {
+    read_journal(&app_file(&app_handle, JOURNAL_FILE_NAME)?).map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+pub fn undo_replacements(
+    app_handle: tauri::AppHandle,
+    replacements: Vec<Replacement>,
+) -> Result<Vec<ErrorInfo>, String> {
+    undo_files(&replacements, &app_file(&app_handle, JOURNAL_FILE_NAME)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+    use tempfile::tempdir;
+
+    fn create_duplicates(dir: &Path) -> DedupeRequest {
+        fs::write(dir.join("original"), "content").unwrap();
+        fs::write(dir.join("duplicate"), "content").unwrap();
+        filetime::set_file_mtime(dir.join("duplicate"), FileTime::from_unix_time(1000, 0)).unwrap();
+        DedupeRequest {
+            original: EntryPath::from(dir.join("original")),
+            duplicates: vec![EntryPath::from(dir.join("duplicate"))],
+            hash: get_file_content_hash(dir.join("original")).unwrap(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hardlink_and_undo() {
+        let dir = tempdir().unwrap();
+        let journal = dir.path().join("journal.json");
+        let request = create_duplicates(dir.path());
+        let same_file = || {
+            file_id(&fs::metadata(dir.path().join("original")).unwrap())
+                == file_id(&fs::metadata(dir.path().join("duplicate")).unwrap())
+        };
+
+        let duplicate = dir.path().join("duplicate");
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&duplicate, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        // Not every file system of the temporary directory supports extended attributes
+        let has_xattr = xattr::set(&duplicate, "user.bumblebee", b"value").is_ok();
+
+        let result = dedupe_files(&[request], DedupeMethod::Hardlink, &journal).unwrap();
+        assert_eq!(result.errors, vec![]);
+        assert!(same_file());
+        assert_eq!(read_journal(&journal).unwrap(), result.replacements);
+
+        let errors = undo_files(&result.replacements, &journal).unwrap();
+        assert_eq!(errors, vec![]);
+        assert!(!same_file());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("duplicate")).unwrap(),
+            "content"
+        );
+        let modified = fs::metadata(dir.path().join("duplicate"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(
+            FileTime::from_system_time(modified),
+            FileTime::from_unix_time(1000, 0)
+        );
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&duplicate).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        if has_xattr {
+            assert_eq!(
+                xattr::get(&duplicate, "user.bumblebee").unwrap(),
+                Some(b"value".to_vec())
+            );
+        }
+        assert_eq!(read_journal(&journal).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn nothing_is_replaced_if_the_journal_cannot_be_read() {
+        let dir = tempdir().unwrap();
+        let journal = dir.path().join("journal.json");
+        fs::write(&journal, "not a journal").unwrap();
+        let request = create_duplicates(dir.path());
+
+        assert!(dedupe_files(&[request], DedupeMethod::Copy, &journal).is_err());
+        let modified = fs::metadata(dir.path().join("duplicate"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(
+            FileTime::from_system_time(modified),
+            FileTime::from_unix_time(1000, 0)
+        );
+        assert_eq!(fs::read_to_string(&journal).unwrap(), "not a journal");
+    }
+
+    #[test]
+    fn reflink_falls_back_to_copy() {
+        let dir = tempdir().unwrap();
+        let journal = dir.path().join("journal.json");
+        let request = create_duplicates(dir.path());
+
+        let result = dedupe_files(&[request], DedupeMethod::Reflink, &journal).unwrap();
+        assert_eq!(result.errors, vec![]);
+        assert_ne!(result.replacements[0].method, DedupeMethod::Hardlink);
+        // The journal records the method which was actually used
+        assert_eq!(read_journal(&journal).unwrap(), result.replacements);
+        // The metadata of the replaced file is kept
+        let modified = fs::metadata(dir.path().join("duplicate"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(
+            FileTime::from_system_time(modified),
+            FileTime::from_unix_time(1000, 0)
+        );
+    }
+
+    #[test]
+    fn changed_files_are_not_replaced() {
+        let dir = tempdir().unwrap();
+        let journal = dir.path().join("journal.json");
+        let request = create_duplicates(dir.path());
+        fs::write(dir.path().join("duplicate"), "changed").unwrap();
+
+        let result = dedupe_files(&[request], DedupeMethod::Hardlink, &journal).unwrap();
+        assert_eq!(result.replacements, vec![]);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("duplicate")).unwrap(),
+            "changed"
+        );
+    }
+}
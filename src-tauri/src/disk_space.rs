@@ -1,10 +1,27 @@
 use crate::debounce::Debounce;
-use crate::fs_entry::{DirEntry, ErrorEntry, FileEntry, FsEntry};
-use std::path::Path;
+use crate::duplicates::{build_glob_set, file_id};
+use crate::entry_path::EntryPath;
+use crate::fs_entry::{DirEntry, ErrorEntry, FileEntry, FsEntry, LinkEntry};
+use globset::GlobSet;
+use rayon::prelude::*;
+use std::collections::{hash_map, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64};
 use std::sync::{atomic, Arc, Mutex};
 use std::time::Duration;
-use std::{fs, io};
+use std::{fs, io, thread};
 use tauri::Manager;
+use walkdir::WalkDir;
+
+/// How often the thread waiting for a scan checks whether it is finished
+const PROGRESS_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The scan itself doesn't recurse, but the functions walking its result (like serialization) do.
+/// Limiting the depth protects them against a stack overflow in pathologically deep trees.
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Larger values for `ScanOptions::max_depth` are lowered to this
+const MAX_DEPTH_LIMIT: usize = 1024;
 
 pub struct SavedAnalysisResult(pub Arc<Mutex<Option<FsEntry>>>);
 
@@ -15,17 +32,317 @@ struct ProgressPayload {
     total_size_found: u64,
 }
 
-struct Context<'a, 'b> {
-    report_progress: &'a mut Debounce<'a, ProgressPayload>,
-    should_abort: &'b ShouldAbort,
-    number_of_files_found: u64,
-    total_size_found: u64,
+#[derive(Debug, Default, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// Number of threads scanning in parallel, `None` uses one thread per CPU
+    pub threads: Option<usize>,
+    /// Directories deeper than this are only summarized, see `summarize_directory()`.
+    /// `None` uses `DEFAULT_MAX_DEPTH`, values above `MAX_DEPTH_LIMIT` are lowered to it.
+    pub max_depth: Option<usize>,
+    pub symlink_policy: SymlinkPolicy,
+    /// Don't descend into directories on other file systems, like `du -x`
+    pub one_file_system: bool,
+    /// Glob patterns matched against the path relative to the scanned directory. Matching entries
+    /// are left out, matching directories are not read.
+    pub exclude_patterns: Vec<String>,
+    /// Leave out files and directories whose name starts with a dot
+    pub skip_hidden: bool,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum SymlinkPolicy {
+    /// Leave symbolic links out of the result
+    Skip,
+    /// Show symbolic links with the size of the link itself
+    #[default]
+    CountLink,
+    /// Treat symbolic links like their target. Links to a directory containing the link are
+    /// reported as errors. A directory reached more than once is only counted where it was found
+    /// first, see `scan_directories()`.
+    Follow,
+}
+
+/// Counters shared by all threads of a scan
+#[derive(Debug, Default)]
+struct Progress {
+    number_of_files_found: AtomicU64,
+    total_size_found: AtomicU64,
+    current_path: Mutex<String>,
+}
+
+impl Progress {
+    fn count_file(&self, size: u64) {
+        self.number_of_files_found
+            .fetch_add(1, atomic::Ordering::Relaxed);
+        self.total_size_found
+            .fetch_add(size, atomic::Ordering::Relaxed);
+    }
+
+    fn payload(&self) -> ProgressPayload {
+        ProgressPayload {
+            path: self.current_path.lock().unwrap().clone(),
+            number_of_files_found: self.number_of_files_found.load(atomic::Ordering::Relaxed),
+            total_size_found: self.total_size_found.load(atomic::Ordering::Relaxed),
+        }
+    }
+}
+
+/// A link to a file with more than one hardlink. Of all links to the same file only the one with
+/// the smallest path is counted, so the result doesn't depend on the order of the scan.
+struct Hardlink {
+    path: PathBuf,
+    size: u64,
+    allocated_size: u64,
+    /// The directory which contains the link if it was summarized by `summarize_directory()`
+    summarized_in: Option<PathBuf>,
+}
+
+struct Context<'a> {
+    progress: &'a Progress,
+    should_abort: &'a AtomicBool,
+    max_depth: usize,
+    symlink_policy: SymlinkPolicy,
+    /// The counted link of each hardlinked file found so far, by (device, inode)
+    hardlinks: &'a Mutex<HashMap<(u64, u64), Hardlink>>,
+    /// Hardlinked files which are counted in summarized directories outside of the scanned
+    /// directory, see `OutsideHardlinks`
+    counted_elsewhere: &'a HashSet<(u64, u64)>,
+    /// Device of the scanned directory if the scan should stay on its file system
+    root_device: Option<u64>,
+    root: &'a Path,
+    excludes: &'a GlobSet,
+    skip_hidden: bool,
+}
+
+impl Context<'_> {
+    fn is_excluded(&self, path: &Path) -> bool {
+        if self.skip_hidden
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            return true;
+        }
+        match path.strip_prefix(self.root) {
+            Ok(sub_path) => !sub_path.as_os_str().is_empty() && self.excludes.is_match(sub_path),
+            Err(_) => false,
+        }
+    }
+
+    /// Remember a file with more than one hardlink, see `Hardlink`. Its size is only added once
+    /// all links are known, by `count_hardlinks()`.
+    ///
+    /// Returns whether this is the first link found to the file.
+    fn add_hardlink(
+        &self,
+        path: &Path,
+        metadata: &fs::Metadata,
+        summarized_in: Option<&Path>,
+    ) -> bool {
+        let Some(id) = file_id(metadata) else {
+            return true;
+        };
+        if self.counted_elsewhere.contains(&id) {
+            return false;
+        }
+        let link = Hardlink {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            allocated_size: allocated_size(metadata),
+            summarized_in: summarized_in.map(Path::to_path_buf),
+        };
+        insert_hardlink(&mut self.hardlinks.lock().unwrap(), id, link)
+    }
+}
+
+/// Keep the link with the smallest path of each file, see `Hardlink`.
+/// Returns whether this is the first link to the file.
+fn insert_hardlink(
+    hardlinks: &mut HashMap<(u64, u64), Hardlink>,
+    id: (u64, u64),
+    link: Hardlink,
+) -> bool {
+    match hardlinks.entry(id) {
+        hash_map::Entry::Vacant(entry) => {
+            entry.insert(link);
+            true
+        }
+        hash_map::Entry::Occupied(mut entry) => {
+            if link.path < entry.get().path {
+                entry.insert(link);
+            }
+            false
+        }
+    }
+}
+
+fn aborted_entry(path: EntryPath) -> FsEntry {
+    FsEntry::Error(ErrorEntry {
+        path: Some(path),
+        size: None,
+        content: None,
+        reason: "Aborted".to_string(),
+    })
+}
+
+/// Space used on disk, `st_blocks` is counted in 512 byte units independent of the block size
+#[cfg(unix)]
+fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+#[cfg(unix)]
+fn number_of_links(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn number_of_links(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+/// Sum up the files below `path` without keeping them. Walks without recursion, so it is used for
+/// directories past `Context::max_depth`.
+fn summarize_directory(context: &Context, path: &Path) -> FsEntry {
+    let mut size = 0;
+    let mut allocated = 0;
+    let mut shared_size = 0;
+    let mut number_of_files = 0;
+    let mut unreadable_entries = 0;
+    let walk = WalkDir::new(path)
+        .follow_links(context.symlink_policy == SymlinkPolicy::Follow)
+        .same_file_system(context.root_device.is_some());
+    let entries = walk
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !context.is_excluded(entry.path()));
+    for entry in entries {
+        if context.should_abort.load(atomic::Ordering::Relaxed) {
+            return aborted_entry(EntryPath::from(path));
+        }
+        // Loops are reported as errors by walkdir too
+        let Ok(entry) = entry else {
+            unreadable_entries += 1;
+            continue;
+        };
+        let file_type = entry.file_type();
+        let is_counted = file_type.is_file()
+            || (file_type.is_symlink() && context.symlink_policy == SymlinkPolicy::CountLink);
+        if !is_counted {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            unreadable_entries += 1;
+            continue;
+        };
+        number_of_files += 1;
+        if number_of_links(&metadata) > 1 {
+            shared_size += metadata.len();
+            let is_first = context.add_hardlink(entry.path(), &metadata, Some(path));
+            context
+                .progress
+                .count_file(if is_first { metadata.len() } else { 0 });
+            continue;
+        }
+        context.progress.count_file(metadata.len());
+        size += metadata.len();
+        allocated += allocated_size(&metadata);
+    }
+    FsEntry::Dir(DirEntry::truncated(
+        EntryPath::from(path),
+        size,
+        allocated,
+        shared_size,
+        number_of_files,
+        unreadable_entries,
+    ))
+}
+
+fn error_entry(path: &Path, reason: String) -> FsEntry {
+    FsEntry::Error(ErrorEntry {
+        path: Some(EntryPath::from(path)),
+        size: None,
+        content: None,
+        reason,
+    })
+}
+
+fn link_entry(path: &Path, metadata: &fs::Metadata) -> FsEntry {
+    match fs::read_link(path) {
+        Ok(target) => FsEntry::Link(LinkEntry {
+            path: EntryPath::from(path),
+            target: EntryPath::from(target),
+            size: metadata.len(),
+            allocated_size: allocated_size(metadata),
+        }),
+        Err(err) => error_entry(path, err.to_string()),
+    }
+}
+
+/// The directories from the scanned directory down to the current one, to detect symlink loops
+struct Ancestors {
+    id: Option<(u64, u64)>,
+    parent: Option<Arc<Ancestors>>,
+}
+
+impl Ancestors {
+    fn contains(&self, id: (u64, u64)) -> bool {
+        let mut ancestor = Some(self);
+        while let Some(current) = ancestor {
+            if current.id == Some(id) {
+                return true;
+            }
+            ancestor = current.parent.as_deref();
+        }
+        false
+    }
+}
+
+/// A directory whose entries still have to be read by `scan_directories()`
+struct PendingDirectory {
+    path: PathBuf,
+    /// The entries of the scanned directory have depth 1
+    depth: usize,
+    /// Starts with the directory itself
+    ancestors: Arc<Ancestors>,
+    /// Index of the parent in the list of read directories and of the entry for this directory in
+    /// the content of the parent. `None` for the scanned directory.
+    parent: Option<(usize, usize)>,
+    /// The directory was reached through a symbolic link, see `SymlinkPolicy::Follow`
+    is_link: bool,
+}
+
+/// What `analyse_entry()` found
+enum Analysed {
+    Entry(FsEntry),
+    Directory(PendingDirectory),
+}
+
+/// A directory whose entries were read, see `scan_directories()`
+struct ReadDirectory {
+    path: EntryPath,
+    parent: Option<(usize, usize)>,
+    /// Subdirectories are placeholders until they are read themselves. `Err` replaces the
+    /// directory, for example if it could not be read.
+    content: Result<Vec<FsEntry>, FsEntry>,
 }
 
+/// `depth` is the depth of `entry`, the entries of the scanned directory have depth 1.
+/// `ancestors` starts with the directory containing `entry`.
+/// Returns `None` for entries which are skipped.
 fn analyse_entry(
-    context: &mut Context,
+    context: &Context,
     entry: Result<fs::DirEntry, io::Error>,
-) -> Result<FsEntry, FsEntry> {
+    depth: usize,
+    ancestors: &Arc<Ancestors>,
+) -> Result<Option<Analysed>, FsEntry> {
     let entry = entry.map_err(|err| {
         FsEntry::Error(ErrorEntry {
             path: None,
@@ -34,79 +351,338 @@ fn analyse_entry(
             reason: err.to_string(),
         })
     })?;
+    let path = entry.path();
+    if context.is_excluded(&path) {
+        return Ok(None);
+    }
 
-    let metadata = entry.metadata().map_err(|err| {
-        FsEntry::Error(ErrorEntry {
-            path: Some(entry.path().to_string_lossy().to_string()),
-            size: None,
-            content: None,
-            reason: err.to_string(),
-        })
-    })?;
+    // Does not follow symlinks
+    let mut metadata = entry
+        .metadata()
+        .map_err(|err| error_entry(&path, err.to_string()))?;
+    let is_link = metadata.file_type().is_symlink();
 
-    if metadata.is_file() {
-        Ok(FsEntry::File(FileEntry {
-            path: entry.path().to_string_lossy().to_string(),
-            size: metadata.len(),
-        }))
+    if is_link {
+        match context.symlink_policy {
+            SymlinkPolicy::Skip => return Ok(None),
+            SymlinkPolicy::CountLink => {
+                context.progress.count_file(metadata.len());
+                return Ok(Some(Analysed::Entry(link_entry(&path, &metadata))));
+            }
+            SymlinkPolicy::Follow => {
+                metadata =
+                    fs::metadata(&path).map_err(|err| error_entry(&path, err.to_string()))?;
+            }
+        }
+    }
 
-        // TODO: is_symlink is unstable
-        // } else if metaadata.is_symlink() {
+    if !metadata.is_dir() {
+        let links = number_of_links(&metadata);
+        let is_first = links <= 1 || context.add_hardlink(&path, &metadata, None);
+        context
+            .progress
+            .count_file(if is_first { metadata.len() } else { 0 });
+        Ok(Some(Analysed::Entry(FsEntry::File(FileEntry {
+            path: EntryPath::from(path),
+            size: metadata.len(),
+            allocated_size: allocated_size(&metadata),
+            links,
+            // Decided by `count_hardlinks()` after the scan
+            already_counted: links > 1,
+        }))))
+    } else if context.root_device.is_some()
+        && file_id(&metadata).map(|(device, _)| device) != context.root_device
+    {
+        Ok(Some(Analysed::Entry(FsEntry::Dir(DirEntry::mount_point(
+            EntryPath::from(path),
+        )))))
+    } else if depth >= context.max_depth {
+        Ok(Some(Analysed::Entry(summarize_directory(context, &path))))
     } else {
-        // TODO: Implement a limit for the recursion depth to protect against a stack overflow
-        Ok(analyze_directory_recursive(context, entry.path()))
+        let id = file_id(&metadata);
+        if id.is_some_and(|id| ancestors.contains(id)) {
+            return Err(error_entry(
+                &path,
+                "Symbolic link loop, the directory contains itself".to_string(),
+            ));
+        }
+        Ok(Some(Analysed::Directory(PendingDirectory {
+            path,
+            depth,
+            ancestors: Arc::new(Ancestors {
+                id,
+                parent: Some(Arc::clone(ancestors)),
+            }),
+            parent: None,
+            is_link,
+        })))
     }
 }
 
-fn analyze_directory_recursive<P: AsRef<Path>>(
-    context: &mut Context,
-    directory_path: P,
-) -> FsEntry {
-    let path_str = directory_path.as_ref().to_string_lossy().to_string();
-    if context.should_abort.0.load(atomic::Ordering::Relaxed) {
-        return FsEntry::Error(ErrorEntry {
-            path: Some(path_str),
-            size: None,
-            content: None,
-            reason: "Aborted".to_string(),
-        });
+/// Analyse the entries of a directory in parallel. Subdirectories are returned to be read later.
+fn read_directory(
+    context: &Context,
+    directory: &PendingDirectory,
+) -> Result<Vec<Analysed>, FsEntry> {
+    let path = EntryPath::from(directory.path.as_path());
+    if context.should_abort.load(atomic::Ordering::Relaxed) {
+        return Err(aborted_entry(path));
+    }
+    if let Ok(mut current_path) = context.progress.current_path.try_lock() {
+        *current_path = path.display();
     }
-    context.report_progress.maybe_run(ProgressPayload {
-        path: path_str.clone(),
-        number_of_files_found: context.number_of_files_found,
-        total_size_found: context.total_size_found,
-    });
 
-    let read_dir = fs::read_dir(directory_path);
-    if let Err(err) = read_dir {
-        return FsEntry::Error(ErrorEntry {
-            path: Some(path_str),
-            size: None,
-            content: None,
-            reason: err.to_string(),
+    let read_dir: Vec<_> = fs::read_dir(&directory.path)
+        .map_err(|err| {
+            FsEntry::Error(ErrorEntry {
+                path: Some(path),
+                size: None,
+                content: None,
+                reason: err.to_string(),
+            })
+        })?
+        .collect();
+
+    Ok(read_dir
+        .into_par_iter()
+        .filter_map(|entry| {
+            match analyse_entry(context, entry, directory.depth + 1, &directory.ancestors) {
+                Ok(analysed) => analysed,
+                Err(entry) => Some(Analysed::Entry(entry)),
+            }
+        })
+        .collect())
+}
+
+/// Shown instead of a directory behind a followed link if the directory was already counted.
+/// The size of the link itself is not counted, like for all followed links.
+fn counted_elsewhere_entry(path: &Path) -> FsEntry {
+    match fs::read_link(path) {
+        Ok(target) => FsEntry::Link(LinkEntry {
+            path: EntryPath::from(path),
+            target: EntryPath::from(target),
+            size: 0,
+            allocated_size: 0,
+        }),
+        Err(err) => error_entry(path, err.to_string()),
+    }
+}
+
+/// Scan the tree below `root` level by level with a work list instead of recursion, so deep trees
+/// can't overflow the stack. The directories of one level are read in parallel.
+///
+/// Like hardlinked files, a directory reached more than once through links is only counted once.
+/// Directories behind links are read after all other directories, ordered by path, so a directory
+/// is counted where it really is if it is part of the scan.
+fn scan_directories(context: &Context, root: PendingDirectory) -> FsEntry {
+    // Parents always come before their children
+    let mut directories: Vec<ReadDirectory> = Vec::new();
+    let mut visited = HashSet::new();
+    let mut behind_links: Vec<PendingDirectory> = Vec::new();
+    let mut level = vec![root];
+    loop {
+        if level.is_empty() {
+            if behind_links.is_empty() {
+                break;
+            }
+            level = std::mem::take(&mut behind_links);
+            level.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        level.retain(|directory| {
+            let Some(id) = directory.ancestors.id else {
+                return true;
+            };
+            if visited.insert(id) {
+                return true;
+            }
+            if let Some((parent, position)) = directory.parent {
+                if let Ok(content) = &mut directories[parent].content {
+                    // Real directories are found twice through bind mounts only, they stay empty
+                    if directory.is_link {
+                        content[position] = counted_elsewhere_entry(&directory.path);
+                    }
+                }
+            }
+            false
         });
+
+        let read: Vec<_> = level
+            .into_par_iter()
+            .map(|directory| {
+                let analysed = read_directory(context, &directory);
+                (directory, analysed)
+            })
+            .collect();
+
+        let mut next_level = Vec::new();
+        for (directory, analysed) in read {
+            let index = directories.len();
+            let path = EntryPath::from(directory.path);
+            let content = analysed.map(|analysed| {
+                let mut content = Vec::with_capacity(analysed.len());
+                for entry in analysed {
+                    match entry {
+                        Analysed::Entry(entry) => content.push(entry),
+                        Analysed::Directory(mut subdirectory) => {
+                            subdirectory.parent = Some((index, content.len()));
+                            let placeholder = EntryPath::from(subdirectory.path.as_path());
+                            content.push(FsEntry::Dir(DirEntry::new(placeholder, vec![])));
+                            if subdirectory.is_link {
+                                behind_links.push(subdirectory);
+                            } else {
+                                next_level.push(subdirectory);
+                            }
+                        }
+                    }
+                }
+                content
+            });
+            directories.push(ReadDirectory {
+                path,
+                parent: directory.parent,
+                content,
+            });
+        }
+        level = next_level;
     }
 
-    let read_dir = read_dir.unwrap();
+    // Every directory is complete once all directories after it were put into their parents
+    while let Some(directory) = directories.pop() {
+        let entry = match directory.content {
+            Ok(content) => FsEntry::Dir(DirEntry::new(directory.path, content)),
+            Err(entry) => entry,
+        };
+        match directory.parent {
+            Some((parent, position)) => {
+                if let Ok(content) = &mut directories[parent].content {
+                    content[position] = entry;
+                }
+            }
+            None => return entry,
+        }
+    }
+    unreachable!("The scanned directory is always read")
+}
 
-    let mut entries: Vec<FsEntry> = Vec::new();
-    for entry in read_dir {
-        match analyse_entry(context, entry) {
-            Ok(e) | Err(e) => entries.push(e),
+/// Add the size of the counted link of every hardlinked file to `entry`, see `Hardlink`.
+/// Returns the paths of the counted links which are not in summarized directories.
+fn count_hardlinks(
+    entry: &mut FsEntry,
+    hardlinks: HashMap<(u64, u64), Hardlink>,
+) -> HashSet<EntryPath> {
+    let mut counted_files = HashSet::new();
+    let mut summarized = HashMap::new();
+    for link in hardlinks.into_values() {
+        match link.summarized_in {
+            Some(dir) => {
+                let (size, allocated_size) =
+                    summarized.entry(EntryPath::from(dir)).or_insert((0, 0));
+                *size += link.size;
+                *allocated_size += link.allocated_size;
+            }
+            None => {
+                counted_files.insert(EntryPath::from(link.path));
+            }
         }
     }
+    entry.count_hardlinks(&counted_files, &summarized);
+    counted_files
+}
+
+/// Scan `path` with a pool of `options.threads` threads.
+///
+/// `report_progress` is called from the calling thread while the scan is running. The returned
+/// entry is incomplete if `should_abort` was set during the scan.
+fn scan(
+    path: &Path,
+    options: &ScanOptions,
+    should_abort: &AtomicBool,
+    report_progress: &mut Debounce<ProgressPayload>,
+) -> Result<FsEntry, String> {
+    let (entry, _) = scan_subtree(
+        path,
+        path,
+        options,
+        OutsideHardlinks::default(),
+        should_abort,
+        report_progress,
+    )?;
+    Ok(entry)
+}
 
-    let dir = DirEntry::new(path_str, entries);
-    context.number_of_files_found += dir.iter_files().count() as u64;
-    context.total_size_found += dir.iter_files().map(|entry| entry.size()).sum::<u64>();
+/// Scan `path` as if it was reached while scanning `root`, so the depth limit, the exclude
+/// patterns and the one-file-system option apply as they would for `root`.
+///
+/// The links in `outside` compete with the links found in `path` for being counted, see
+/// `hardlinks_outside()`. Returns the entry and the paths of all links which are counted now.
+fn scan_subtree(
+    root: &Path,
+    path: &Path,
+    options: &ScanOptions,
+    outside: OutsideHardlinks,
+    should_abort: &AtomicBool,
+    report_progress: &mut Debounce<ProgressPayload>,
+) -> Result<(FsEntry, HashSet<EntryPath>), String> {
+    let depth = path
+        .strip_prefix(root)
+        .map_err(|_| format!("{} is not within {}", path.display(), root.display()))?
+        .components()
+        .count();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads.unwrap_or(0))
+        .build()
+        .map_err(|err| err.to_string())?;
+    let excludes = build_glob_set(&options.exclude_patterns)?;
+    let progress = Progress::default();
+    let hardlinks = Mutex::new(outside.links);
+    let id = |path: &Path| fs::metadata(path).ok().and_then(|m| file_id(&m));
+    let context = Context {
+        progress: &progress,
+        should_abort,
+        max_depth: options
+            .max_depth
+            .unwrap_or(DEFAULT_MAX_DEPTH)
+            .min(MAX_DEPTH_LIMIT),
+        symlink_policy: options.symlink_policy,
+        hardlinks: &hardlinks,
+        counted_elsewhere: &outside.counted_in_summarized,
+        root_device: id(root)
+            .filter(|_| options.one_file_system)
+            .map(|(device, _)| device),
+        root,
+        excludes: &excludes,
+        skip_hidden: options.skip_hidden,
+    };
+    let root = PendingDirectory {
+        path: path.to_path_buf(),
+        depth,
+        ancestors: Arc::new(Ancestors {
+            id: id(path),
+            parent: None,
+        }),
+        parent: None,
+        is_link: false,
+    };
 
-    FsEntry::Dir(dir)
+    let mut entry = thread::scope(|scope| {
+        let scan = scope.spawn(|| pool.install(|| scan_directories(&context, root)));
+        while !scan.is_finished() {
+            report_progress.maybe_run(progress.payload());
+            thread::sleep(PROGRESS_POLL_INTERVAL);
+        }
+        scan.join()
+            .map_err(|_| "Scanning thread panicked".to_string())
+    })?;
+    let counted_files = count_hardlinks(&mut entry, hardlinks.into_inner().unwrap());
+    Ok((entry, counted_files))
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct AnalyseResult {
     result: FsEntry,
     duration: u64,
+    /// The options used for the scan, including the filters which were applied
+    options: ScanOptions,
 }
 
 #[tauri::command(async)]
@@ -115,34 +691,29 @@ pub fn analyze_disk_usage(
     should_abort: tauri::State<ShouldAbort>,
     saved_result: tauri::State<SavedAnalysisResult>,
     path: String,
-) -> AnalyseResult {
+    options: Option<ScanOptions>,
+) -> Result<AnalyseResult, String> {
     should_abort.0.store(false, atomic::Ordering::Relaxed);
     use std::time::Instant;
     let now = Instant::now();
     let func = |payload| app_handle.emit_all("progress", payload).unwrap();
     let mut report_progress = Debounce::new(Duration::from_millis(100), &func);
-    let result = analyze_directory_recursive(
-        &mut Context {
-            report_progress: &mut report_progress,
-            should_abort: &should_abort,
-            number_of_files_found: 0,
-            total_size_found: 0,
-        },
+    let options = options.unwrap_or_default();
+    let result = scan(
         Path::new(&path),
-    );
+        &options,
+        &should_abort.0,
+        &mut report_progress,
+    )?;
     let duration = now.elapsed().as_millis();
 
     if should_abort.0.load(atomic::Ordering::Relaxed) {
         // No sense to send the data collected so far, return an empty result
-        return AnalyseResult {
-            result: FsEntry::Error(ErrorEntry {
-                path: Some(path),
-                size: None,
-                content: None,
-                reason: "Aborted".to_string(),
-            }),
+        return Ok(AnalyseResult {
+            result: aborted_entry(EntryPath::from(path)),
             duration: duration as u64,
-        };
+            options,
+        });
     }
 
     let flat_result = match result {
@@ -152,10 +723,11 @@ pub fn analyze_disk_usage(
 
     *saved_result.0.lock().unwrap() = Some(result);
 
-    AnalyseResult {
+    Ok(AnalyseResult {
         result: flat_result,
         duration: duration as u64,
-    }
+        options,
+    })
 }
 #[derive(Debug)]
 pub struct ShouldAbort(pub atomic::AtomicBool);
@@ -167,7 +739,7 @@ pub fn abort(should_abort: tauri::State<'_, ShouldAbort>) {
 
 #[tauri::command]
 pub fn load_nested_directory(
-    path: String,
+    path: EntryPath,
     saved_result: tauri::State<'_, SavedAnalysisResult>,
 ) -> Option<FsEntry> {
     let root_entry = &*saved_result.0.lock().unwrap();
@@ -177,3 +749,589 @@ pub fn load_nested_directory(
 
     None
 }
+
+/// Links to hardlinked files outside of a rescanned directory, see `hardlinks_outside()`
+#[derive(Default)]
+struct OutsideHardlinks {
+    /// The link with the smallest path of each file, by (device, inode)
+    links: HashMap<(u64, u64), Hardlink>,
+    /// Files counted in summarized directories. Their totals can't change without scanning them
+    /// again, so the files stay counted there.
+    counted_in_summarized: HashSet<(u64, u64)>,
+}
+
+/// Collect the hardlinked files of `entry` (with whether they are inside of `path`) and the
+/// summarized directories with hardlinked files outside of `path`
+fn collect_hardlinks<'a>(
+    entry: &'a FsEntry,
+    path: &EntryPath,
+    files: &mut Vec<(&'a FileEntry, bool)>,
+    summarized: &mut Vec<&'a EntryPath>,
+) {
+    match entry {
+        FsEntry::File(f) if f.links > 1 => files.push((f, f.path.starts_with(path))),
+        // Only truncated directories have hardlinked files without content
+        FsEntry::Dir(d) if d.content().is_empty() && entry.shared_size() > 0 => {
+            if let Some(dir) = entry.path().filter(|dir| !dir.starts_with(path)) {
+                summarized.push(dir);
+            }
+        }
+        FsEntry::Dir(d) => {
+            for entry in d.content() {
+                collect_hardlinks(entry, path, files, summarized);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The links outside of `path` to the hardlinked files of `saved_result`. After `path` was
+/// scanned again, the link with the smallest path of each file is counted, wherever it is.
+///
+/// Links within summarized directories are not known individually. If none of the known links
+/// of such a file was counted, it is counted in its summarized directory.
+fn hardlinks_outside(saved_result: &FsEntry, path: &EntryPath) -> OutsideHardlinks {
+    let mut files = Vec::new();
+    let mut summarized = Vec::new();
+    collect_hardlinks(saved_result, path, &mut files, &mut summarized);
+
+    let mut outside = OutsideHardlinks::default();
+    let mut counted = HashSet::new();
+    for (file, is_inside) in files {
+        // Links which were removed in the meantime can't be counted anymore
+        let Some(id) = fs::metadata(file.path.as_path())
+            .ok()
+            .and_then(|m| file_id(&m))
+        else {
+            continue;
+        };
+        if !file.already_counted {
+            counted.insert(id);
+        }
+        if !is_inside {
+            let link = Hardlink {
+                path: file.path.as_path().to_path_buf(),
+                size: file.size,
+                allocated_size: file.allocated_size,
+                summarized_in: None,
+            };
+            insert_hardlink(&mut outside.links, id, link);
+        }
+    }
+
+    for dir in summarized {
+        for entry in WalkDir::new(dir.as_path()).into_iter().flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !entry.file_type().is_file() || number_of_links(&metadata) <= 1 {
+                continue;
+            }
+            if let Some(id) = file_id(&metadata).filter(|id| !counted.contains(id)) {
+                outside.links.remove(&id);
+                outside.counted_in_summarized.insert(id);
+            }
+        }
+    }
+    outside
+}
+
+/// Put the rescanned `entry` into `saved_result` and update which links of hardlinked files are
+/// counted, `counted_files` is returned by `scan_subtree()`
+fn replace_rescanned_entry(
+    saved_result: &mut FsEntry,
+    entry: FsEntry,
+    counted_files: &HashSet<EntryPath>,
+) -> Result<(), String> {
+    match saved_result {
+        FsEntry::Dir(_) if entry.path() == saved_result.path() => *saved_result = entry,
+        FsEntry::Dir(d) => {
+            let path = entry.path().cloned().unwrap_or_default();
+            if !d.replace_entry(entry) {
+                return Err(format!("{} is not part of the saved scan", path));
+            }
+            // The counted link of a file may have moved into or out of the rescanned directory
+            saved_result.count_hardlinks(counted_files, &HashMap::new());
+        }
+        _ => return Err("There is no scan to update".to_string()),
+    }
+    Ok(())
+}
+
+/// Scan the directory at `path` again and replace it in the saved result, for example after
+/// something was deleted. The totals of all directories above it are updated.
+///
+/// Returns the new entry with its top levels like `load_nested_directory()`.
+#[tauri::command(async)]
+pub fn rescan_subtree(
+    app_handle: tauri::AppHandle,
+    should_abort: tauri::State<ShouldAbort>,
+    saved_result: tauri::State<SavedAnalysisResult>,
+    path: EntryPath,
+    options: Option<ScanOptions>,
+) -> Result<FsEntry, String> {
+    // Don't keep the saved result locked during the scan, so it can still be browsed
+    let (root_path, outside) = match &*saved_result.0.lock().unwrap() {
+        Some(root @ FsEntry::Dir(_)) => (
+            root.path().cloned().unwrap_or_default(),
+            hardlinks_outside(root, &path),
+        ),
+        _ => return Err("There is no scan to update".to_string()),
+    };
+
+    should_abort.0.store(false, atomic::Ordering::Relaxed);
+    let func = |payload| app_handle.emit_all("progress", payload).unwrap();
+    let mut report_progress = Debounce::new(Duration::from_millis(100), &func);
+    let (entry, counted_files) = scan_subtree(
+        root_path.as_path(),
+        path.as_path(),
+        &options.unwrap_or_default(),
+        outside,
+        &should_abort.0,
+        &mut report_progress,
+    )?;
+    if should_abort.0.load(atomic::Ordering::Relaxed) {
+        return Err("Aborted".to_string());
+    }
+
+    let flat_entry = match entry {
+        FsEntry::Dir(ref d) => FsEntry::Dir(d.clone_flat(2)),
+        ref entry => entry.clone(),
+    };
+    match &mut *saved_result.0.lock().unwrap() {
+        Some(saved_result) => replace_rescanned_entry(saved_result, entry, &counted_files)?,
+        None => return Err("There is no scan to update".to_string()),
+    }
+    Ok(flat_entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn scan_directory(path: &Path, threads: usize, should_abort: bool) -> FsEntry {
+        let options = ScanOptions {
+            threads: Some(threads),
+            ..Default::default()
+        };
+        scan_with_options(path, &options, should_abort)
+    }
+
+    /// Scan `path` within the scan of `root` again like `rescan_subtree()`
+    fn rescan(root: &mut FsEntry, path: &Path) {
+        let report = |_| {};
+        let (entry, counted_files) = scan_subtree(
+            root.path().unwrap().as_path(),
+            path,
+            &ScanOptions::default(),
+            hardlinks_outside(root, &EntryPath::from(path)),
+            &AtomicBool::new(false),
+            &mut Debounce::new(Duration::from_millis(100), &report),
+        )
+        .unwrap();
+        replace_rescanned_entry(root, entry, &counted_files).unwrap();
+    }
+
+    fn scan_with_options(path: &Path, options: &ScanOptions, should_abort: bool) -> FsEntry {
+        let report = |_| {};
+        scan(
+            path,
+            options,
+            &AtomicBool::new(should_abort),
+            &mut Debounce::new(Duration::from_millis(100), &report),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parallel_scan_builds_same_tree() {
+        let dir = tempdir().unwrap();
+        for i in 0..5 {
+            let sub_dir = dir.path().join(format!("dir{}", i)).join("nested");
+            fs::create_dir_all(&sub_dir).unwrap();
+            fs::write(sub_dir.join("file"), vec![0; i * 10]).unwrap();
+        }
+        fs::write(dir.path().join("file"), "abc").unwrap();
+
+        let single = scan_directory(dir.path(), 1, false);
+        let parallel = scan_directory(dir.path(), 4, false);
+        assert_eq!(single, parallel);
+        assert_eq!(parallel.size(), 103);
+        assert_eq!(parallel.number_of_files(), 6);
+    }
+
+    #[test]
+    fn directories_past_max_depth_are_summarized() {
+        let dir = tempdir().unwrap();
+        let deep = dir.path().join("a").join("b").join("c");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("file1"), "abc").unwrap();
+        fs::write(deep.join("file2"), "de").unwrap();
+
+        let options = ScanOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let result = scan_with_options(dir.path(), &options, false);
+        assert_eq!(result.size(), 5);
+        assert_eq!(result.number_of_files(), 2);
+        let allocated_size = result.allocated_size();
+        let FsEntry::Dir(root) = result else {
+            panic!("Expected a directory")
+        };
+        assert_eq!(
+            root.get_entry_by_path(EntryPath::from(dir.path().join("a").join("b"))),
+            Some(&DirEntry::truncated(
+                EntryPath::from(dir.path().join("a").join("b")),
+                5,
+                allocated_size,
+                0,
+                2,
+                0
+            ))
+        );
+    }
+
+    #[test]
+    fn deep_tree_does_not_overflow_the_stack() {
+        let dir = tempdir().unwrap();
+        let mut deep = dir.path().to_path_buf();
+        for _ in 0..DEFAULT_MAX_DEPTH + 10 {
+            deep.push("d");
+        }
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("file"), "abc").unwrap();
+
+        let result = scan_directory(dir.path(), 2, false);
+        assert_eq!(result.size(), 3);
+        assert_eq!(result.number_of_files(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn errors_in_summarized_directories_are_counted() {
+        let dir = tempdir().unwrap();
+        let sub_dir = dir.path().join("a").join("b");
+        fs::create_dir_all(&sub_dir).unwrap();
+        fs::write(sub_dir.join("file"), "abc").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("a"), sub_dir.join("loop")).unwrap();
+
+        let options = ScanOptions {
+            max_depth: Some(1),
+            symlink_policy: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+        let result = scan_with_options(dir.path(), &options, false);
+        let allocated_size = result.allocated_size();
+        let FsEntry::Dir(root) = result else {
+            panic!("Expected a directory")
+        };
+        // The link back to "a" is a loop
+        assert_eq!(
+            root.get_entry_by_path(EntryPath::from(dir.path().join("a"))),
+            Some(&DirEntry::truncated(
+                EntryPath::from(dir.path().join("a")),
+                3,
+                allocated_size,
+                0,
+                1,
+                1
+            ))
+        );
+    }
+
+    #[test]
+    fn max_depth_is_limited() {
+        let dir = tempdir().unwrap();
+        let mut deep = dir.path().to_path_buf();
+        for _ in 0..MAX_DEPTH_LIMIT + 10 {
+            deep.push("d");
+        }
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("file"), "abc").unwrap();
+
+        let options = ScanOptions {
+            threads: Some(2),
+            max_depth: Some(usize::MAX),
+            ..Default::default()
+        };
+        let result = scan_with_options(dir.path(), &options, false);
+        assert_eq!(result.size(), 3);
+        assert_eq!(result.number_of_files(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_policies() {
+        let dir = tempdir().unwrap();
+        let sub_dir = dir.path().join("dir");
+        fs::create_dir(&sub_dir).unwrap();
+        fs::write(sub_dir.join("file"), "abc").unwrap();
+        std::os::unix::fs::symlink(&sub_dir, dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), sub_dir.join("loop")).unwrap();
+
+        let scan_with_policy = |symlink_policy| {
+            let options = ScanOptions {
+                symlink_policy,
+                ..Default::default()
+            };
+            scan_with_options(dir.path(), &options, false)
+        };
+
+        let skipped = scan_with_policy(SymlinkPolicy::Skip);
+        assert_eq!(skipped.size(), 3);
+        assert_eq!(skipped.number_of_files(), 1);
+
+        let counted = scan_with_policy(SymlinkPolicy::CountLink);
+        assert_eq!(counted.number_of_files(), 3);
+        let FsEntry::Dir(root) = counted else {
+            panic!("Expected a directory")
+        };
+        let counted_sub_dir = root
+            .get_entry_by_path(EntryPath::from(sub_dir.as_path()))
+            .unwrap()
+            .clone();
+        // The file and the link to the scanned directory
+        let link_size = dir.path().as_os_str().len() as u64;
+        assert_eq!(FsEntry::Dir(counted_sub_dir).size(), 3 + link_size);
+
+        // "link" leads to "dir" which is already counted, "loop" ends up at the scanned directory
+        // and is an error
+        let followed = scan_with_policy(SymlinkPolicy::Follow);
+        assert_eq!(followed.size(), 3);
+        assert_eq!(followed.number_of_files(), 3);
+        let FsEntry::Dir(root) = followed else {
+            panic!("Expected a directory")
+        };
+        assert!(root.content().iter().any(|entry| matches!(
+            entry,
+            FsEntry::Link(link) if link.path == EntryPath::from(dir.path().join("link"))
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_behind_links_are_counted_once() {
+        let dir = tempdir().unwrap();
+        let outside = dir.path().join("outside");
+        let scanned = dir.path().join("scanned");
+        fs::create_dir(&outside).unwrap();
+        fs::create_dir(&scanned).unwrap();
+        fs::write(outside.join("file"), "abcd").unwrap();
+        std::os::unix::fs::symlink(&outside, scanned.join("b")).unwrap();
+        std::os::unix::fs::symlink(&outside, scanned.join("a")).unwrap();
+
+        let options = ScanOptions {
+            symlink_policy: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+        let result = scan_with_options(&scanned, &options, false);
+        assert_eq!(result.size(), 4);
+        let FsEntry::Dir(root) = result else {
+            panic!("Expected a directory")
+        };
+        // The link with the smallest path is followed
+        assert!(root
+            .get_entry_by_path(EntryPath::from(scanned.join("a")))
+            .is_some());
+        assert!(root.content().iter().any(|entry| matches!(
+            entry,
+            FsEntry::Link(link) if link.path == EntryPath::from(scanned.join("b"))
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sparse_file_allocates_less_than_its_size() {
+        let dir = tempdir().unwrap();
+        let file = fs::File::create(dir.path().join("sparse")).unwrap();
+        file.set_len(100 * 1024 * 1024).unwrap();
+
+        let result = scan_directory(dir.path(), 1, false);
+        assert_eq!(result.size(), 100 * 1024 * 1024);
+        assert!(result.allocated_size() < result.size());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hardlinks_are_counted_once() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        fs::create_dir(dir.path().join("b")).unwrap();
+        fs::write(dir.path().join("a").join("file"), "abc").unwrap();
+        fs::hard_link(
+            dir.path().join("a").join("file"),
+            dir.path().join("b").join("file"),
+        )
+        .unwrap();
+        fs::write(dir.path().join("other"), "de").unwrap();
+
+        let result = scan_directory(dir.path(), 2, false);
+        assert_eq!(result.size(), 5);
+        assert_eq!(result.shared_size(), 6);
+        assert_eq!(result.number_of_files(), 3);
+
+        // The link with the smallest path is counted, independent of the order of the scan
+        let FsEntry::Dir(root) = result else {
+            panic!("Expected a directory")
+        };
+        let size_of = |root: &DirEntry, name: &str| {
+            let entry = root.get_entry_by_path(EntryPath::from(dir.path().join(name)));
+            FsEntry::Dir(entry.unwrap().clone()).size()
+        };
+        assert_eq!(size_of(&root, "a"), 3);
+        assert_eq!(size_of(&root, "b"), 0);
+        for threads in [1, 4, 8] {
+            assert_eq!(
+                scan_directory(dir.path(), threads, false),
+                FsEntry::Dir(root.clone())
+            );
+        }
+
+        // Also if one of the links is in a summarized directory
+        let options = ScanOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let FsEntry::Dir(summarized) = scan_with_options(dir.path(), &options, false) else {
+            panic!("Expected a directory")
+        };
+        assert_eq!(FsEntry::Dir(summarized.clone()).size(), 5);
+        assert_eq!(size_of(&summarized, "a"), 3);
+        assert_eq!(size_of(&summarized, "b"), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn other_file_systems_are_skipped() {
+        let dir = tempdir().unwrap();
+        let link = dir.path().join("proc");
+        std::os::unix::fs::symlink("/proc", &link).unwrap();
+
+        let options = ScanOptions {
+            symlink_policy: SymlinkPolicy::Follow,
+            one_file_system: true,
+            ..Default::default()
+        };
+        let FsEntry::Dir(root) = scan_with_options(dir.path(), &options, false) else {
+            panic!("Expected a directory")
+        };
+        let path = EntryPath::from(link.as_path());
+        assert_eq!(
+            root.get_entry_by_path(path.clone()),
+            Some(&DirEntry::mount_point(path))
+        );
+    }
+
+    #[test]
+    fn excluded_entries_are_not_scanned() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("node_modules").join("lib")).unwrap();
+        fs::write(
+            dir.path().join("node_modules").join("lib").join("file"),
+            "abc",
+        )
+        .unwrap();
+        fs::write(dir.path().join("file.log"), "abc").unwrap();
+        fs::write(dir.path().join(".hidden"), "abc").unwrap();
+        fs::write(dir.path().join("file"), "abc").unwrap();
+
+        let options = ScanOptions {
+            exclude_patterns: vec!["node_modules".to_string(), "*.log".to_string()],
+            skip_hidden: true,
+            ..Default::default()
+        };
+        let result = scan_with_options(dir.path(), &options, false);
+        assert_eq!(result.number_of_files(), 1);
+        assert_eq!(result.size(), 3);
+    }
+
+    #[test]
+    fn rescanned_subtree_updates_ancestors() {
+        let dir = tempdir().unwrap();
+        let sub_dir = dir.path().join("a").join("b");
+        fs::create_dir_all(&sub_dir).unwrap();
+        fs::write(sub_dir.join("file1"), "abc").unwrap();
+        fs::write(sub_dir.join("file2"), "de").unwrap();
+        fs::write(dir.path().join("file"), "f").unwrap();
+        let mut root = scan_directory(dir.path(), 2, false);
+
+        fs::remove_file(sub_dir.join("file1")).unwrap();
+        rescan(&mut root, &sub_dir);
+
+        let FsEntry::Dir(root) = root else {
+            panic!("Expected a directory")
+        };
+        let a = root
+            .get_entry_by_path(EntryPath::from(dir.path().join("a")))
+            .unwrap()
+            .clone();
+        assert_eq!(FsEntry::Dir(a).size(), 2);
+        assert_eq!(FsEntry::Dir(root.clone()).size(), 3);
+        assert_eq!(FsEntry::Dir(root).number_of_files(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rescan_does_not_count_hardlinks_twice() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        fs::create_dir(dir.path().join("b")).unwrap();
+        fs::write(dir.path().join("a").join("file"), "abc").unwrap();
+        fs::hard_link(
+            dir.path().join("a").join("file"),
+            dir.path().join("b").join("file"),
+        )
+        .unwrap();
+        let mut root = scan_directory(dir.path(), 2, false);
+        assert_eq!(root.size(), 3);
+
+        // The file is counted in "a", which is outside of the rescanned "b" and the other way round
+        for name in ["b", "a"] {
+            rescan(&mut root, &dir.path().join(name));
+            assert_eq!(root.size(), 3);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rescan_counts_another_link_if_the_counted_one_was_deleted() {
+        let dir = tempdir().unwrap();
+        for name in ["a", "b", "c"] {
+            fs::create_dir(dir.path().join(name)).unwrap();
+        }
+        fs::write(dir.path().join("a").join("file"), "abc").unwrap();
+        for name in ["b", "c"] {
+            fs::hard_link(
+                dir.path().join("a").join("file"),
+                dir.path().join(name).join("file"),
+            )
+            .unwrap();
+        }
+        let mut root = scan_directory(dir.path(), 2, false);
+        assert_eq!(root.size(), 3);
+
+        // The counted link is in "a"
+        fs::remove_file(dir.path().join("a").join("file")).unwrap();
+        rescan(&mut root, &dir.path().join("a"));
+        assert_eq!(root.size(), 3);
+        let FsEntry::Dir(root) = root else {
+            panic!("Expected a directory")
+        };
+        let b = root
+            .get_entry_by_path(EntryPath::from(dir.path().join("b")))
+            .unwrap()
+            .clone();
+        assert_eq!(FsEntry::Dir(b).size(), 3);
+    }
+
+    #[test]
+    fn aborted_scan() {
+        let dir = tempdir().unwrap();
+        assert_eq!(
+            scan_directory(dir.path(), 2, true),
+            aborted_entry(EntryPath::from(dir.path()))
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use crate::disk_space::ShouldAbort;
+use crate::entry_path::EntryPath;
+use crate::{get_file_content_hash, sha256_digest, ErrorInfo};
+use data_encoding::HEXUPPER;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tauri::Manager;
+use walkdir::WalkDir;
+
+/// Number of bytes at the start of a file which are hashed to rule out most candidates cheaply
+const PARTIAL_HASH_SIZE: u64 = 4096;
+
+#[derive(Debug, Default, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DuplicateOptions {
+    /// Glob patterns matched against the path relative to the searched directory.
+    /// Matching directories are not descended into.
+    pub ignore_patterns: Vec<String>,
+    /// Smaller files are ignored. Empty files are always ignored.
+    pub min_size: u64,
+}
+
+/// Files with identical content
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    pub size: u64,
+    /// Sorted, contains at least two paths
+    pub paths: Vec<EntryPath>,
+    /// Bytes which could be saved by keeping only one of the files
+    pub wasted_bytes: u64,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DuplicateSummary {
+    number_of_groups: u64,
+    wasted_bytes: u64,
+    errors: Vec<ErrorInfo>,
+    aborted: bool,
+    duration: u64,
+}
+
+fn error_info(path: &Path, message: String) -> ErrorInfo {
+    ErrorInfo {
+        path: EntryPath::from(path),
+        message,
+    }
+}
+
+pub fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|err| err.to_string())?);
+    }
+    builder.build().map_err(|err| err.to_string())
+}
+
+/// Identifies a file independent of its path, so hardlinks to the same file are only counted once
+#[cfg(unix)]
+pub fn file_id(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Walk all `roots` and group the files by their size. Groups with a single file are dropped.
+fn group_by_size(
+    roots: &[String],
+    options: &DuplicateOptions,
+    ignore: &GlobSet,
+    should_abort: &AtomicBool,
+    errors: &mut Vec<ErrorInfo>,
+) -> HashMap<u64, Vec<PathBuf>> {
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let mut seen_paths: HashSet<PathBuf> = HashSet::new();
+    let mut seen_ids: HashSet<(u64, u64)> = HashSet::new();
+
+    for root in roots {
+        let walk = WalkDir::new(root).into_iter().filter_entry(|entry| {
+            let sub_path = entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or_else(|_| entry.path());
+            sub_path.as_os_str().is_empty() || !ignore.is_match(sub_path)
+        });
+        for entry in walk {
+            if should_abort.load(Ordering::Relaxed) {
+                return HashMap::new();
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = err.path().unwrap_or_else(|| Path::new(root));
+                    errors.push(error_info(path, err.to_string()));
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    errors.push(error_info(entry.path(), err.to_string()));
+                    continue;
+                }
+            };
+            let size = metadata.len();
+            if size == 0 || size < options.min_size {
+                continue;
+            }
+            // Overlapping roots and hardlinks would show up as duplicates of themselves
+            if !seen_paths.insert(entry.path().to_path_buf()) {
+                continue;
+            }
+            if let Some(id) = file_id(&metadata) {
+                if !seen_ids.insert(id) {
+                    continue;
+                }
+            }
+            by_size
+                .entry(size)
+                .or_default()
+                .push(entry.path().to_path_buf());
+        }
+    }
+
+    by_size.retain(|_, paths| paths.len() > 1);
+    by_size
+}
+
+fn get_partial_hash(path: &Path) -> Result<String, io::Error> {
+    let reader = File::open(path)?.take(PARTIAL_HASH_SIZE);
+    let digest = sha256_digest(reader)?;
+    Ok(HEXUPPER.encode(digest.as_ref()))
+}
+
+/// Split `paths` into groups with the same hash. Groups with a single file are dropped.
+/// Returns no groups if the search was aborted.
+fn group_by_hash(
+    paths: Vec<PathBuf>,
+    hash: impl Fn(&Path) -> Result<String, io::Error>,
+    should_abort: &AtomicBool,
+    errors: &mut Vec<ErrorInfo>,
+) -> Vec<(String, Vec<PathBuf>)> {
+    let mut by_hash: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for path in paths {
+        // Hashing a group of large files may take long, so check before every file
+        if should_abort.load(Ordering::Relaxed) {
+            return Vec::new();
+        }
+        match hash(&path) {
+            Ok(hash) => by_hash.entry(hash).or_default().push(path),
+            Err(err) => errors.push(error_info(&path, err.to_string())),
+        }
+    }
+    by_hash
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect()
+}
+
+/// Find all files with identical content below `roots`.
+///
+/// Files are grouped by size first, then by the hash of their first bytes and only then by the
+/// hash of the whole content, so most files are never read completely. Every group is passed to
+/// `on_group` as soon as it is known, the largest files first.
+pub fn find_duplicate_groups(
+    roots: &[String],
+    options: &DuplicateOptions,
+    should_abort: &AtomicBool,
+    on_group: &mut dyn FnMut(DuplicateGroup),
+) -> Result<(Vec<ErrorInfo>, bool), String> {
+    let ignore = build_glob_set(&options.ignore_patterns)?;
+    let mut errors = Vec::new();
+    let mut by_size = group_by_size(roots, options, &ignore, should_abort, &mut errors);
+
+    let mut sizes: Vec<u64> = by_size.keys().copied().collect();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    for size in sizes {
+        if should_abort.load(Ordering::Relaxed) {
+            return Ok((errors, true));
+        }
+        let paths = by_size.remove(&size).unwrap_or_default();
+        let candidates = if size > PARTIAL_HASH_SIZE {
+            group_by_hash(paths, get_partial_hash, should_abort, &mut errors)
+                .into_iter()
+                .flat_map(|(_, paths)| paths)
+                .collect()
+        } else {
+            // The partial hash would already be the full hash
+            paths
+        };
+
+        let mut groups = group_by_hash(
+            candidates,
+            |path| get_file_content_hash(path),
+            should_abort,
+            &mut errors,
+        );
+        groups.sort_by(|a, b| a.0.cmp(&b.0));
+        for (hash, paths) in groups {
+            let mut paths: Vec<EntryPath> = paths.into_iter().map(EntryPath::from).collect();
+            paths.sort();
+            on_group(DuplicateGroup {
+                hash,
+                size,
+                wasted_bytes: size * (paths.len() as u64 - 1),
+                paths,
+            });
+        }
+    }
+
+    Ok((errors, should_abort.load(Ordering::Relaxed)))
+}
+
+/// Find files with identical content in all `paths`.
+///
+/// Each group of duplicates is sent as a `duplicate-group` event as soon as it is found.
+/// Can be stopped with `abort()`.
+#[tauri::command(async)]
+pub fn find_duplicates(
+    app_handle: tauri::AppHandle,
+    should_abort: tauri::State<ShouldAbort>,
+    paths: Vec<String>,
+    options: Option<DuplicateOptions>,
+) -> Result<DuplicateSummary, String> {
+    should_abort.0.store(false, Ordering::Relaxed);
+    let now = Instant::now();
+    let mut number_of_groups = 0;
+    let mut wasted_bytes = 0;
+
+    let (errors, aborted) = find_duplicate_groups(
+        &paths,
+        &options.unwrap_or_default(),
+        &should_abort.0,
+        &mut |group| {
+            number_of_groups += 1;
+            wasted_bytes += group.wasted_bytes;
+            if let Err(err) = app_handle.emit_all("duplicate-group", group) {
+                eprintln!("Could not send duplicate group: {}", err);
+            }
+        },
+    )?;
+
+    Ok(DuplicateSummary {
+        number_of_groups,
+        wasted_bytes,
+        errors,
+        aborted,
+        duration: now.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn find(roots: &[String], options: &DuplicateOptions) -> Vec<DuplicateGroup> {
+        let mut groups = Vec::new();
+        let (errors, aborted) =
+            find_duplicate_groups(roots, options, &AtomicBool::new(false), &mut |group| {
+                groups.push(group)
+            })
+            .unwrap();
+        assert_eq!(errors, vec![]);
+        assert!(!aborted);
+        groups
+    }
+
+    #[test]
+    fn finds_duplicates_in_multiple_directories() {
+        let dir = tempdir().unwrap();
+        let dir_a = dir.path().join("a");
+        let dir_b = dir.path().join("b");
+        fs::create_dir_all(dir_a.join("ignored")).unwrap();
+        fs::create_dir_all(&dir_b).unwrap();
+        let large = vec![7u8; PARTIAL_HASH_SIZE as usize + 10];
+        let mut large_other = large.clone();
+        *large_other.last_mut().unwrap() = 8;
+        fs::write(dir_a.join("large"), &large).unwrap();
+        fs::write(dir_b.join("large"), &large).unwrap();
+        // Same start and size, only differs at the end
+        fs::write(dir_b.join("large_other"), &large_other).unwrap();
+        fs::write(dir_a.join("small"), "abc").unwrap();
+        fs::write(dir_b.join("small"), "abc").unwrap();
+        fs::write(dir_a.join("ignored").join("small"), "abc").unwrap();
+
+        let roots = vec![
+            dir_a.to_string_lossy().to_string(),
+            dir_b.to_string_lossy().to_string(),
+        ];
+        let options = DuplicateOptions {
+            ignore_patterns: vec!["ignored".to_string()],
+            ..Default::default()
+        };
+        let groups = find(&roots, &options);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[0].paths,
+            vec![
+                EntryPath::from(dir_a.join("large")),
+                EntryPath::from(dir_b.join("large"))
+            ]
+        );
+        assert_eq!(groups[0].wasted_bytes, large.len() as u64);
+        assert_eq!(groups[1].size, 3);
+        assert_eq!(groups[1].paths.len(), 2);
+
+        let options = DuplicateOptions {
+            min_size: 4,
+            ..Default::default()
+        };
+        assert_eq!(find(&roots, &options).len(), 1);
+    }
+
+    #[test]
+    fn aborted_search_returns_nothing() {
+        let mut groups = Vec::new();
+        let (_, aborted) = find_duplicate_groups(
+            &["./test/01_test_files_match".to_string()],
+            &DuplicateOptions::default(),
+            &AtomicBool::new(true),
+            &mut |group| groups.push(group),
+        )
+        .unwrap();
+        assert!(aborted);
+        assert_eq!(groups, vec![]);
+    }
+
+    #[test]
+    fn hashing_stops_when_aborted() {
+        let should_abort = AtomicBool::new(false);
+        let hashed = std::cell::Cell::new(0);
+        let groups = group_by_hash(
+            vec![PathBuf::from("a"), PathBuf::from("b"), PathBuf::from("c")],
+            |_| {
+                hashed.set(hashed.get() + 1);
+                should_abort.store(true, Ordering::Relaxed);
+                Ok("hash".to_string())
+            },
+            &should_abort,
+            &mut Vec::new(),
+        );
+        assert_eq!(hashed.get(), 1);
+        assert_eq!(groups, vec![]);
+    }
+}
@@ -0,0 +1,175 @@
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A path which survives the round trip to the frontend even if it isn't valid UTF-8.
+///
+/// Valid UTF-8 paths are serialized as plain strings. All other paths are serialized as an object
+/// containing the raw bytes and a lossy `display` version for showing it to the user. The
+/// frontend passes back whatever it received, so the original path can always be restored.
+///
+/// Unlike `PathBuf` the comparison is done byte wise, so `a/b` and `a//b` are different paths.
+/// This keeps the order of the results the same as with plain strings.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct EntryPath(OsString);
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum SerializedPath {
+    Utf8(String),
+    Raw { display: String, bytes: Vec<u8> },
+}
+
+impl EntryPath {
+    pub fn as_path(&self) -> &Path {
+        Path::new(&self.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Lossy version of the path, only to be used for showing it to the user
+    pub fn display(&self) -> String {
+        self.0.to_string_lossy().to_string()
+    }
+
+    /// Whether `self` is `base` or is located within `base`. The comparison is done on whole
+    /// path components, so `dir2` doesn't start with `dir`.
+    pub fn starts_with(&self, base: &EntryPath) -> bool {
+        self.as_path().starts_with(base.as_path())
+    }
+}
+
+impl AsRef<Path> for EntryPath {
+    fn as_ref(&self) -> &Path {
+        self.as_path()
+    }
+}
+
+impl fmt::Display for EntryPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.to_string_lossy())
+    }
+}
+
+impl From<&str> for EntryPath {
+    fn from(path: &str) -> Self {
+        EntryPath(OsString::from(path))
+    }
+}
+
+impl From<String> for EntryPath {
+    fn from(path: String) -> Self {
+        EntryPath(OsString::from(path))
+    }
+}
+
+impl From<&OsStr> for EntryPath {
+    fn from(path: &OsStr) -> Self {
+        EntryPath(path.to_os_string())
+    }
+}
+
+impl From<&Path> for EntryPath {
+    fn from(path: &Path) -> Self {
+        EntryPath(path.as_os_str().to_os_string())
+    }
+}
+
+impl From<PathBuf> for EntryPath {
+    fn from(path: PathBuf) -> Self {
+        EntryPath(path.into_os_string())
+    }
+}
+
+impl PartialEq<str> for EntryPath {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for EntryPath {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[cfg(unix)]
+pub fn to_bytes(path: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+// Paths on other platforms are not byte sequences. Windows paths which are not valid UTF-16 are
+// very rare, so they are only converted lossy.
+#[cfg(not(unix))]
+pub fn to_bytes(path: &OsStr) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).to_string())
+}
+
+impl serde::Serialize for EntryPath {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.to_str() {
+            Some(path) => SerializedPath::Utf8(path.to_string()),
+            None => SerializedPath::Raw {
+                display: self.display(),
+                bytes: to_bytes(&self.0),
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for EntryPath {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match SerializedPath::deserialize(deserializer)? {
+            SerializedPath::Utf8(path) => EntryPath::from(path),
+            SerializedPath::Raw { bytes, .. } => EntryPath(from_bytes(bytes)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_utf8_path() {
+        let path = EntryPath::from("dir/file.txt");
+        let json = serde_json::to_string(&path).unwrap();
+        assert_eq!(json, "\"dir/file.txt\"");
+        assert_eq!(serde_json::from_str::<EntryPath>(&json).unwrap(), path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serialize_non_utf8_path() {
+        let path = EntryPath(from_bytes(b"dir/f\xFFile.txt".to_vec()));
+        let json = serde_json::to_string(&path).unwrap();
+        assert_eq!(
+            json,
+            "{\"display\":\"dir/f\u{FFFD}ile.txt\",\"bytes\":[100,105,114,47,102,255,105,108,101,46,116,120,116]}"
+        );
+        assert_eq!(serde_json::from_str::<EntryPath>(&json).unwrap(), path);
+    }
+
+    #[test]
+    fn starts_with_whole_components() {
+        let dir = EntryPath::from("dir");
+        assert!(EntryPath::from("dir/file").starts_with(&dir));
+        assert!(dir.starts_with(&dir));
+        assert!(!EntryPath::from("dir2").starts_with(&dir));
+    }
+}
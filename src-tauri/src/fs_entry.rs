@@ -1,6 +1,9 @@
+use crate::entry_path::EntryPath;
+use std::collections::{HashMap, HashSet};
+
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ErrorEntry {
-    pub path: Option<String>,
+    pub path: Option<EntryPath>,
     pub size: Option<u64>,
     pub content: Option<Vec<FsEntry>>,
     pub reason: String,
@@ -8,36 +11,146 @@ pub struct ErrorEntry {
 
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct FileEntry {
-    pub path: String,
+    pub path: EntryPath,
+    /// Apparent size, the length of the content
+    pub size: u64,
+    /// Space used on disk, smaller than `size` for sparse or compressed files
+    pub allocated_size: u64,
+    /// Number of hardlinks to the file, 1 if it is not hardlinked
+    pub links: u64,
+    /// Another hardlink to the same file was found before, so the size is not counted again
+    pub already_counted: bool,
+}
+
+/// A symbolic link which was not followed
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LinkEntry {
+    pub path: EntryPath,
+    pub target: EntryPath,
+    /// Size of the link itself, 0 for a followed link to a directory which was counted elsewhere
     pub size: u64,
+    pub allocated_size: u64,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DirEntry {
-    path: String,
+    path: EntryPath,
     size: u64,
+    allocated_size: u64,
+    /// Size of the hardlinked files below this directory, each link counted separately
+    shared_size: u64,
     number_of_files: u64,
     content: Vec<FsEntry>,
+    /// The content was not scanned, only `size` and `number_of_files` are known
+    truncated: bool,
+    /// Entries of a truncated directory which could not be read, they are missing in the totals
+    #[serde(default)]
+    unreadable_entries: u64,
+    /// Another file system is mounted here, it was not scanned
+    mount_point: bool,
 }
 
 impl DirEntry {
-    pub fn new(path: String, entries: Vec<FsEntry>) -> Self {
-        let size: u64 = entries.iter().map(|entry| entry.size()).sum();
-        let number_of_files = entries.iter().map(|entry| entry.number_of_files()).sum();
+    pub fn new(path: EntryPath, entries: Vec<FsEntry>) -> Self {
+        let mut dir = Self {
+            path,
+            content: entries,
+            size: 0,
+            allocated_size: 0,
+            shared_size: 0,
+            number_of_files: 0,
+            truncated: false,
+            unreadable_entries: 0,
+            mount_point: false,
+        };
+        dir.update_totals();
+        dir
+    }
 
+    fn update_totals(&mut self) {
+        let entries = &self.content;
+        self.size = entries.iter().map(|entry| entry.size()).sum();
+        self.allocated_size = entries.iter().map(|entry| entry.allocated_size()).sum();
+        self.shared_size = entries.iter().map(|entry| entry.shared_size()).sum();
+        self.number_of_files = entries.iter().map(|entry| entry.number_of_files()).sum();
+    }
+
+    /// Replace the entry with the same path as `entry` somewhere below this directory and update
+    /// the totals of all directories on the way. Returns false if there is no such entry.
+    pub fn replace_entry(&mut self, entry: FsEntry) -> bool {
+        let path = match entry.path() {
+            Some(path) => path.clone(),
+            None => return false,
+        };
+        let existing = self
+            .content
+            .iter_mut()
+            .find(|existing| existing.path().is_some_and(|p| path.starts_with(p)));
+        let replaced = match existing {
+            Some(existing) if existing.path() == Some(&path) => {
+                *existing = entry;
+                true
+            }
+            Some(FsEntry::Dir(d)) => d.replace_entry(entry),
+            _ => false,
+        };
+        if replaced {
+            self.update_totals();
+        }
+        replaced
+    }
+
+    /// See `FsEntry::count_hardlinks()`
+    fn count_hardlinks(
+        &mut self,
+        counted_files: &HashSet<EntryPath>,
+        summarized: &HashMap<EntryPath, (u64, u64)>,
+    ) {
+        if self.truncated {
+            if let Some((size, allocated_size)) = summarized.get(&self.path) {
+                self.size += size;
+                self.allocated_size += allocated_size;
+            }
+            return;
+        }
+        for entry in &mut self.content {
+            entry.count_hardlinks(counted_files, summarized);
+        }
+        self.update_totals();
+    }
+
+    /// A directory whose content is not kept
+    pub fn truncated(
+        path: EntryPath,
+        size: u64,
+        allocated_size: u64,
+        shared_size: u64,
+        number_of_files: u64,
+        unreadable_entries: u64,
+    ) -> Self {
         Self {
             path,
-            content: entries,
+            content: vec![],
             size,
+            allocated_size,
+            shared_size,
             number_of_files,
+            truncated: true,
+            unreadable_entries,
+            mount_point: false,
         }
     }
 
-    /// Iterator over self.contents which returns only FileEntry entries
-    pub fn iter_files(&self) -> impl Iterator<Item = &FsEntry> {
-        self.content
-            .iter()
-            .filter(|entry| matches!(entry, FsEntry::File(_)))
+    /// A directory on another file system which was skipped
+    pub fn mount_point(path: EntryPath) -> Self {
+        Self {
+            mount_point: true,
+            ..Self::new(path, vec![])
+        }
+    }
+
+    pub fn content(&self) -> &[FsEntry] {
+        &self.content
     }
 
     /// Clone the current entry with up to `levels_to_keep` depth of its contents
@@ -48,6 +161,7 @@ impl DirEntry {
                 .map(|entry| match entry {
                     FsEntry::File(f) => FsEntry::File(f.clone()),
                     FsEntry::Error(e) => FsEntry::Error(e.clone()),
+                    FsEntry::Link(l) => FsEntry::Link(l.clone()),
                     FsEntry::Dir(d) => FsEntry::Dir(d.clone_flat(levels_to_keep - 1)),
                 })
                 .collect()
@@ -58,15 +172,19 @@ impl DirEntry {
             content,
             path: self.path.clone(),
             size: self.size,
+            allocated_size: self.allocated_size,
+            shared_size: self.shared_size,
             number_of_files: self.number_of_files,
+            truncated: self.truncated,
+            unreadable_entries: self.unreadable_entries,
+            mount_point: self.mount_point,
         }
     }
 
     /// Search for an entry recursivly within the current entry
-    pub fn get_entry_by_path(&self, path: String) -> Option<&Self> {
+    pub fn get_entry_by_path(&self, path: EntryPath) -> Option<&Self> {
         for entry in &self.content {
             if let FsEntry::Dir(d) = entry {
-                dbg!("entry", &d.path);
                 if d.path == path {
                     return Some(d);
                 } else if path.starts_with(&d.path) {
@@ -84,14 +202,62 @@ pub enum FsEntry {
     File(FileEntry),
     Dir(DirEntry),
     Error(ErrorEntry),
+    Link(LinkEntry),
 }
 
 impl FsEntry {
+    /// Count the hardlinked files in `counted_files` and the hardlinked sizes of the summarized
+    /// directories in `summarized`, all other hardlinked files are already counted elsewhere.
+    /// The totals of all directories are updated.
+    pub fn count_hardlinks(
+        &mut self,
+        counted_files: &HashSet<EntryPath>,
+        summarized: &HashMap<EntryPath, (u64, u64)>,
+    ) {
+        match self {
+            FsEntry::File(f) if f.links > 1 => {
+                f.already_counted = !counted_files.contains(&f.path);
+            }
+            FsEntry::Dir(d) => d.count_hardlinks(counted_files, summarized),
+            _ => {}
+        }
+    }
+
+    pub fn path(&self) -> Option<&EntryPath> {
+        match self {
+            FsEntry::File(f) => Some(&f.path),
+            FsEntry::Dir(d) => Some(&d.path),
+            FsEntry::Error(e) => e.path.as_ref(),
+            FsEntry::Link(l) => Some(&l.path),
+        }
+    }
+
+    /// Size counted towards the total, hardlinked files are only counted once
     pub fn size(&self) -> u64 {
         match self {
+            FsEntry::File(f) if f.already_counted => 0,
             FsEntry::File(f) => f.size,
             FsEntry::Dir(d) => d.size,
             FsEntry::Error(_) => 0,
+            FsEntry::Link(l) => l.size,
+        }
+    }
+
+    pub fn allocated_size(&self) -> u64 {
+        match self {
+            FsEntry::File(f) if f.already_counted => 0,
+            FsEntry::File(f) => f.allocated_size,
+            FsEntry::Dir(d) => d.allocated_size,
+            FsEntry::Error(_) => 0,
+            FsEntry::Link(l) => l.allocated_size,
+        }
+    }
+
+    pub fn shared_size(&self) -> u64 {
+        match self {
+            FsEntry::File(f) if f.links > 1 => f.size,
+            FsEntry::Dir(d) => d.shared_size,
+            _ => 0,
         }
     }
 
@@ -99,6 +265,7 @@ impl FsEntry {
         match self {
             FsEntry::File(_) => 1,
             FsEntry::Error(_) => 1,
+            FsEntry::Link(_) => 1,
             FsEntry::Dir(d) => d.number_of_files,
         }
     }
@@ -0,0 +1,247 @@
+use crate::normalize::read_small_file;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// PNG chunks which only contain metadata
+const PNG_METADATA_CHUNKS: [&[u8]; 5] = [b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+
+#[derive(
+    Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+/// An image split into the data which makes up the picture and its metadata segments
+#[derive(Debug, Default)]
+struct SplitImage {
+    content: Vec<u8>,
+    /// Name of the segment and its content
+    metadata: Vec<(String, Vec<u8>)>,
+}
+
+fn jpeg_segment_name(marker: u8, data: &[u8]) -> Option<String> {
+    match marker {
+        0xE1 if data.starts_with(b"Exif\0") => Some("Exif".to_string()),
+        0xE1 if data.starts_with(b"http://ns.adobe.com/xap/1.0/") => Some("XMP".to_string()),
+        0xED => Some("IPTC".to_string()),
+        0xFE => Some("Comment".to_string()),
+        // All other APPn segments (like JFIF, ICC profiles or Adobe's color transform in APP14)
+        // may change how the image is displayed
+        _ => None,
+    }
+}
+
+/// Split a JPEG file at its markers. Everything from the start of the scan (SOS) on is image data.
+fn split_jpeg(bytes: &[u8]) -> Option<SplitImage> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut image = SplitImage::default();
+    image.content.extend_from_slice(&bytes[..2]);
+    let mut pos = 2;
+    loop {
+        if *bytes.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *bytes.get(pos + 1)?;
+        match marker {
+            // Fill byte
+            0xFF => pos += 1,
+            // Markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                image.content.extend_from_slice(&bytes[pos..pos + 2]);
+                pos += 2;
+            }
+            // Start of scan or end of image
+            0xDA | 0xD9 => {
+                image.content.extend_from_slice(&bytes[pos..]);
+                return Some(image);
+            }
+            _ => {
+                let length = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]);
+                // The length includes the two length bytes
+                if length < 2 {
+                    return None;
+                }
+                let end = pos + 2 + length as usize;
+                let segment = bytes.get(pos..end)?;
+                match jpeg_segment_name(marker, &segment[4..]) {
+                    Some(name) => image.metadata.push((name, segment[4..].to_vec())),
+                    None => image.content.extend_from_slice(segment),
+                }
+                pos = end;
+            }
+        }
+    }
+}
+
+fn split_png(bytes: &[u8]) -> Option<SplitImage> {
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+    let mut image = SplitImage::default();
+    image.content.extend_from_slice(PNG_SIGNATURE);
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < bytes.len() {
+        let length = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        // Length, type, data and CRC
+        let chunk = bytes.get(pos..pos + 12 + length)?;
+        let chunk_type = &chunk[4..8];
+        if PNG_METADATA_CHUNKS.contains(&chunk_type) {
+            let name = String::from_utf8_lossy(chunk_type).to_string();
+            image.metadata.push((name, chunk[8..8 + length].to_vec()));
+        } else {
+            image.content.extend_from_slice(chunk);
+        }
+        pos += chunk.len();
+    }
+    Some(image)
+}
+
+fn split_image(bytes: &[u8]) -> Option<(ImageFormat, SplitImage)> {
+    if let Some(image) = split_jpeg(bytes) {
+        return Some((ImageFormat::Jpeg, image));
+    }
+    split_png(bytes).map(|image| (ImageFormat::Png, image))
+}
+
+/// Names of the metadata segments whose content differs
+fn differing_segments(a: SplitImage, b: SplitImage) -> Vec<String> {
+    let group = |metadata: Vec<(String, Vec<u8>)>| {
+        let mut grouped: BTreeMap<String, Vec<Vec<u8>>> = BTreeMap::new();
+        for (name, data) in metadata {
+            grouped.entry(name).or_default().push(data);
+        }
+        grouped
+    };
+    let a = group(a.metadata);
+    let b = group(b.metadata);
+    let names: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| a.get(*name) != b.get(*name))
+        .cloned()
+        .collect()
+}
+
+/// Compare two JPEG or PNG files without their metadata (like Exif, XMP or text chunks).
+///
+/// Returns the format and the names of the differing metadata segments if only the metadata
+/// differs. Returns `None` if the image data differs or the files are no JPEG or PNG images.
+/// The image data is compared as it is stored, so a re-encoded image is not detected as equal.
+pub fn compare_without_metadata(
+    path_a: &Path,
+    path_b: &Path,
+) -> Option<(ImageFormat, Vec<String>)> {
+    let (format_a, image_a) = split_image(&read_small_file(path_a)?)?;
+    let (format_b, image_b) = split_image(&read_small_file(path_b)?)?;
+    if format_a != format_b || image_a.content != image_b.content {
+        return None;
+    }
+    Some((format_a, differing_segments(image_a, image_b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn jpeg(exif: &[u8], scan: &[u8]) -> Vec<u8> {
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(exif);
+        jpeg_with_segment(0xE1, &app1, scan)
+    }
+
+    fn jpeg_with_segment(marker: u8, data: &[u8], scan: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8];
+        bytes.extend_from_slice(&[0xFF, marker]);
+        bytes.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02]);
+        bytes.extend_from_slice(scan);
+        bytes.extend_from_slice(&[0xFF, 0xD9]);
+        bytes
+    }
+
+    fn png_chunk(chunk_type: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(chunk_type);
+        chunk.extend_from_slice(data);
+        // The CRC is not checked
+        chunk.extend_from_slice(&[0, 0, 0, 0]);
+        chunk
+    }
+
+    fn png(text: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend(png_chunk(b"IHDR", &[0; 13]));
+        bytes.extend(png_chunk(b"tEXt", text));
+        bytes.extend(png_chunk(b"IDAT", data));
+        bytes.extend(png_chunk(b"IEND", &[]));
+        bytes
+    }
+
+    fn compare(content_a: &[u8], content_b: &[u8]) -> Option<(ImageFormat, Vec<String>)> {
+        let dir = tempdir().unwrap();
+        let path_a = dir.path().join("a");
+        let path_b = dir.path().join("b");
+        fs::write(&path_a, content_a).unwrap();
+        fs::write(&path_b, content_b).unwrap();
+        compare_without_metadata(&path_a, &path_b)
+    }
+
+    #[test]
+    fn jpeg_metadata_only() {
+        assert_eq!(
+            compare(&jpeg(b"camera 1", b"pixels"), &jpeg(b"camera 2", b"pixels")),
+            Some((ImageFormat::Jpeg, vec!["Exif".to_string()]))
+        );
+        assert_eq!(
+            compare(
+                &jpeg(b"camera 1", b"pixels"),
+                &jpeg(b"camera 1", b"changed")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn jpeg_other_app_segments_are_content() {
+        // APP14 (Adobe) defines the color transform of the image
+        assert_eq!(
+            compare(
+                &jpeg_with_segment(0xEE, b"Adobe\0\x00", b"pixels"),
+                &jpeg_with_segment(0xEE, b"Adobe\0\x01", b"pixels")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn png_metadata_only() {
+        assert_eq!(
+            compare(&png(b"tag 1", b"pixels"), &png(b"tag 2", b"pixels")),
+            Some((ImageFormat::Png, vec!["tEXt".to_string()]))
+        );
+        assert_eq!(
+            compare(&png(b"tag 1", b"pixels"), &png(b"tag 1", b"changed")),
+            None
+        );
+    }
+
+    #[test]
+    fn real_images_differ() {
+        assert_eq!(
+            compare_without_metadata(
+                Path::new("./test/07_different_binary_content/dirA/file1.jpeg"),
+                Path::new("./test/07_different_binary_content/dirB/file1.jpeg"),
+            ),
+            None
+        );
+    }
+}
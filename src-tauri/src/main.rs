@@ -5,6 +5,8 @@
 
 use data_encoding::HEXUPPER;
 use disk_space::SavedAnalysisResult;
+use entry_path::EntryPath;
+use globset::GlobSet;
 use itertools::Itertools;
 use ring::digest::{Context, Digest, SHA256};
 use std::cmp::Ordering;
@@ -12,14 +14,29 @@ use std::collections::HashSet;
 use std::fs::metadata;
 use std::fs::File;
 use std::io::{self, BufReader, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
 use std::time::SystemTime;
 use walkdir::WalkDir;
 
+mod copy;
 mod debounce;
+mod dedupe;
 mod disk_space;
+mod duplicates;
+mod entry_path;
 mod fs_entry;
+mod image;
+mod merge_walk;
+mod merkle;
+mod normalize;
+mod profiles;
+mod resolve;
+mod scan_diff;
+mod scan_file;
+mod snapshot;
+mod structured;
+mod watch;
 
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, serde::Serialize, serde::Deserialize)]
 enum EntryType {
@@ -31,50 +48,152 @@ enum EntryType {
 
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, serde::Serialize, serde::Deserialize)]
 struct EntryTypeMismatch {
-    path: String,
+    path: EntryPath,
     type_in_dir_a: EntryType,
     type_in_dir_b: EntryType,
 }
 
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, serde::Serialize, serde::Deserialize)]
 struct EntryInfo {
-    path: String,
+    path: EntryPath,
 }
 
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, serde::Serialize, serde::Deserialize)]
 struct FileInfo {
-    path: String,
+    path: EntryPath,
     last_modified_in_dir_a: u64, // seconds since UNIX_EPOCH
     last_modified_in_dir_b: u64, // seconds since UNIX_EPOCH
+    size_in_dir_a: u64,
+    size_in_dir_b: u64,
+    /// `size_in_dir_b - size_in_dir_a`
+    size_delta: i64,
+    hash_in_dir_a: String,
+    hash_in_dir_b: String,
+}
+
+impl FileInfo {
+    fn new(
+        path: EntryPath,
+        (last_modified_in_dir_a, size_in_dir_a, hash_in_dir_a): (u64, u64, String),
+        (last_modified_in_dir_b, size_in_dir_b, hash_in_dir_b): (u64, u64, String),
+    ) -> Self {
+        FileInfo {
+            path,
+            last_modified_in_dir_a,
+            last_modified_in_dir_b,
+            size_in_dir_a,
+            size_in_dir_b,
+            size_delta: size_in_dir_b as i64 - size_in_dir_a as i64,
+            hash_in_dir_a,
+            hash_in_dir_b,
+        }
+    }
+}
+
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, serde::Serialize, serde::Deserialize)]
+struct EquivalentInfo {
+    path: EntryPath,
+    /// The normalizations which made both files equal
+    normalizers: Vec<normalize::Normalizer>,
+}
+
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, serde::Serialize, serde::Deserialize)]
+struct StructuredDataInfo {
+    path: EntryPath,
+    format: structured::DataFormat,
+    /// Key paths of the values which differ, like `$.server.port`
+    differing_keys: Vec<String>,
+}
+
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, serde::Serialize, serde::Deserialize)]
+struct MetadataDifferenceInfo {
+    path: EntryPath,
+    format: image::ImageFormat,
+    /// Names of the metadata segments which differ, like `Exif` or `tEXt`
+    differing_segments: Vec<String>,
 }
 
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, serde::Serialize, serde::Deserialize)]
 struct ErrorInfo {
-    path: String,
+    path: EntryPath,
     message: String,
 }
 
+/// How the directory trees are walked by `compare()`
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+enum CompareStrategy {
+    /// Collect all paths of both directories and compare the sets. Needs memory for every path.
+    #[default]
+    InMemory,
+    /// Walk both directories at the same time in sorted order, see `merge_walk::MergeWalk`.
+    /// Needs far less memory for huge directories.
+    MergeWalk,
+    /// Compare hashes of whole directories and only descend into differing ones, see `merkle`.
+    Merkle,
+}
+
+/// Opt-in settings for how the content of files with different hashes is compared
+#[derive(Debug, Default, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct CompareOptions {
+    /// Text files which are equal after these normalizations are reported as `EquivalentContent`
+    normalizers: Vec<normalize::Normalizer>,
+    /// Parse JSON, YAML and TOML files and compare the data instead of the text
+    compare_structured_data: bool,
+    /// Report JPEG and PNG files which only differ in their metadata as `MetadataOnlyDifference`
+    ignore_image_metadata: bool,
+    /// Entries whose path relative to the compared directories matches one of these glob patterns
+    /// are not walked, together with everything below them
+    ignore_patterns: Vec<String>,
+}
+
+impl CompareOptions {
+    /// Nothing is ignored if one of the patterns is invalid, check them with
+    /// `duplicates::build_glob_set()` before comparing
+    fn ignore_set(&self) -> GlobSet {
+        duplicates::build_glob_set(&self.ignore_patterns).unwrap_or_else(|_| GlobSet::empty())
+    }
+}
+
+/// Whether `path` within `dir` matches one of the `CompareOptions::ignore_patterns`
+fn is_ignored(ignore: &GlobSet, dir: &Path, path: &Path) -> bool {
+    match path.strip_prefix(dir) {
+        Ok(sub_path) => !sub_path.as_os_str().is_empty() && ignore.is_match(sub_path),
+        Err(_) => false,
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "type")]
 enum CompareResult {
     CouldNotReadDirectory(ErrorInfo),
     CouldNotCalculateHash(ErrorInfo),
     CouldNotGetLastModified(ErrorInfo),
+    CouldNotGetSize(ErrorInfo),
     MissingInDirA(EntryInfo),
     MissingInDirB(EntryInfo),
     DifferingContent(FileInfo),
+    EquivalentContent(EquivalentInfo),
+    SemanticallyEqual(StructuredDataInfo),
+    DifferingData(StructuredDataInfo),
+    MetadataOnlyDifference(MetadataDifferenceInfo),
     TypeMismatch(EntryTypeMismatch),
 }
 
 impl CompareResult {
-    fn path(&self) -> &String {
+    fn path(&self) -> &EntryPath {
         match self {
             CompareResult::CouldNotReadDirectory(r) => &r.path,
             CompareResult::CouldNotCalculateHash(r) => &r.path,
             CompareResult::CouldNotGetLastModified(r) => &r.path,
+            CompareResult::CouldNotGetSize(r) => &r.path,
             CompareResult::MissingInDirA(r) => &r.path,
             CompareResult::MissingInDirB(r) => &r.path,
             CompareResult::DifferingContent(r) => &r.path,
+            CompareResult::EquivalentContent(r) => &r.path,
+            CompareResult::SemanticallyEqual(r) => &r.path,
+            CompareResult::DifferingData(r) => &r.path,
+            CompareResult::MetadataOnlyDifference(r) => &r.path,
             CompareResult::TypeMismatch(r) => &r.path,
         }
     }
@@ -91,31 +210,47 @@ impl Ord for CompareResult {
     }
 }
 
-fn get_directory_content_recursively(dir: &str) -> (HashSet<String>, Vec<CompareResult>) {
-    let mut filenames: HashSet<String> = HashSet::new();
+/// Collect the paths of all entries at and below `sub_path` within `dir`, an empty `sub_path`
+/// walks the whole directory. The returned paths are relative to `dir`. A missing `sub_path` yields no entries.
+/// Paths matching `ignore` are skipped, see `is_ignored()`.
+fn get_sub_directory_content_recursively(
+    dir: &str,
+    sub_path: &EntryPath,
+    ignore: &GlobSet,
+) -> (HashSet<EntryPath>, Vec<CompareResult>) {
+    let mut filenames: HashSet<EntryPath> = HashSet::new();
     let mut errors: Vec<CompareResult> = Vec::new();
 
-    for result in WalkDir::new(&dir) {
+    let walk_root = if sub_path.is_empty() {
+        Path::new(dir).to_path_buf()
+    } else {
+        let walk_root = Path::new(dir).join(sub_path);
+        if walk_root.symlink_metadata().is_err() {
+            return (filenames, errors);
+        }
+        walk_root
+    };
+
+    let walk = WalkDir::new(&walk_root)
+        .into_iter()
+        .filter_entry(|entry| !is_ignored(ignore, Path::new(dir), entry.path()));
+    for result in walk {
         match result {
             Err(why) => {
                 let error = CompareResult::CouldNotReadDirectory(ErrorInfo {
-                    path: why
-                        .path()
-                        .unwrap_or_else(|| Path::new(""))
-                        .to_string_lossy()
-                        .to_string(),
+                    path: EntryPath::from(why.path().unwrap_or_else(|| Path::new(""))),
                     message: why.to_string(),
                 });
                 errors.push(error);
             }
             Ok(entry) => {
-                let f_name = entry
-                    .path()
-                    .strip_prefix(&dir)
-                    // This should never panic as the path should always start with the base directory
-                    .expect("Path doesn't start with base directory")
-                    .to_string_lossy()
-                    .to_string();
+                let f_name = EntryPath::from(
+                    entry
+                        .path()
+                        .strip_prefix(&dir)
+                        // This should never panic as the path should always start with the base directory
+                        .expect("Path doesn't start with base directory"),
+                );
                 filenames.insert(f_name);
             }
         }
@@ -126,15 +261,15 @@ fn get_directory_content_recursively(dir: &str) -> (HashSet<String>, Vec<Compare
 
 // When handling missing directories / files the initial list contains missing directories and each missing file.
 // In this case we only need to know that the directory is missing, so let's filter out the contents.
-fn remove_subdirectories<'a, I>(paths: I) -> impl Iterator<Item = &'a String>
+fn remove_subdirectories<'a, I>(paths: I) -> impl Iterator<Item = &'a EntryPath>
 where
-    I: Iterator<Item = &'a String>,
+    I: Iterator<Item = &'a EntryPath>,
 {
     paths
-        // Sort entries aphabetially, then only keep an entry if its beginning doesn't match
+        // Sort entries by their components, then only keep an entry if its beginning doesn't match
         // the previous one.
         // This relies on /my_dir appearing before /my_dir/file, in which case the latter would be dropped
-        .sorted()
+        .sorted_by(|a, b| a.as_path().cmp(b.as_path()))
         .coalesce(|a, b| if b.starts_with(a) { Ok(a) } else { Err((a, b)) })
 }
 
@@ -182,45 +317,109 @@ fn get_last_modified_of_file(path: &Path) -> Result<u64, io::Error> {
     Ok(seconds_since_epoch)
 }
 
+/// Check files with different hashes with the comparators enabled in `options`.
+/// Returns `None` if none of them could explain the difference.
+fn compare_file_content(
+    path_a: &Path,
+    path_b: &Path,
+    sub_path: &EntryPath,
+    options: &CompareOptions,
+) -> Option<CompareResult> {
+    if let Some(normalizers) = normalize::find_equivalence(path_a, path_b, &options.normalizers) {
+        return Some(CompareResult::EquivalentContent(EquivalentInfo {
+            path: sub_path.clone(),
+            normalizers,
+        }));
+    }
+
+    if options.compare_structured_data {
+        if let Some((format, differing_keys)) = structured::compare_data(path_a, path_b) {
+            let info = StructuredDataInfo {
+                path: sub_path.clone(),
+                format,
+                differing_keys,
+            };
+            return Some(if info.differing_keys.is_empty() {
+                CompareResult::SemanticallyEqual(info)
+            } else {
+                CompareResult::DifferingData(info)
+            });
+        }
+    }
+
+    if options.ignore_image_metadata {
+        if let Some((format, differing_segments)) = image::compare_without_metadata(path_a, path_b)
+        {
+            return Some(CompareResult::MetadataOnlyDifference(
+                MetadataDifferenceInfo {
+                    path: sub_path.clone(),
+                    format,
+                    differing_segments,
+                },
+            ));
+        }
+    }
+
+    None
+}
+
 fn compare_entry(
     dir_a_path: &str,
     dir_b_path: &str,
-    sub_path: String,
+    sub_path: EntryPath,
+    options: &CompareOptions,
+) -> Result<(), CompareResult> {
+    compare_entry_with(dir_a_path, dir_b_path, sub_path, options, &|path| {
+        get_file_content_hash(path)
+    })
+}
+
+/// Like `compare_entry()` but calculates the content hashes of files with `hash_file`
+fn compare_entry_with(
+    dir_a_path: &str,
+    dir_b_path: &str,
+    sub_path: EntryPath,
+    options: &CompareOptions,
+    hash_file: &dyn Fn(&Path) -> Result<String, io::Error>,
 ) -> Result<(), CompareResult> {
     let path_a = Path::new(&dir_a_path).join(&sub_path);
     let path_b = Path::new(&dir_b_path).join(&sub_path);
     if path_a.is_file() && path_b.is_file() {
-        let hash_a = (get_file_content_hash(&path_a).map_err(|why| {
+        let hash_a = (hash_file(&path_a).map_err(|why| {
             CompareResult::CouldNotCalculateHash(ErrorInfo {
-                path: path_a.to_string_lossy().to_string(),
+                path: EntryPath::from(path_a.as_path()),
                 message: why.to_string(),
             })
         }))?;
-        let hash_b = get_file_content_hash(&path_b).map_err(|why| {
+        let hash_b = hash_file(&path_b).map_err(|why| {
             CompareResult::CouldNotCalculateHash(ErrorInfo {
-                path: path_b.to_string_lossy().to_string(),
+                path: EntryPath::from(path_b.as_path()),
                 message: why.to_string(),
             })
         })?;
         if hash_a != hash_b {
-            let last_modified_in_dir_a = get_last_modified_of_file(&path_a).map_err(|why| {
-                CompareResult::CouldNotGetLastModified(ErrorInfo {
-                    path: path_a.to_string_lossy().to_string(),
-                    message: why.to_string(),
-                })
-            })?;
-            let last_modified_in_dir_b = get_last_modified_of_file(&path_b).map_err(|why| {
-                CompareResult::CouldNotGetLastModified(ErrorInfo {
-                    path: path_b.to_string_lossy().to_string(),
+            if let Some(result) = compare_file_content(&path_a, &path_b, &sub_path, options) {
+                return Err(result);
+            }
+
+            let get_file_details = |path: &Path, hash: String| {
+                let error = |why: io::Error| ErrorInfo {
+                    path: EntryPath::from(path),
                     message: why.to_string(),
-                })
-            })?;
-
-            return Err(CompareResult::DifferingContent(FileInfo {
-                path: sub_path,
-                last_modified_in_dir_a,
-                last_modified_in_dir_b,
-            }));
+                };
+                let last_modified = get_last_modified_of_file(path)
+                    .map_err(|why| CompareResult::CouldNotGetLastModified(error(why)))?;
+                let size = metadata(path)
+                    .map_err(|why| CompareResult::CouldNotGetSize(error(why)))?
+                    .len();
+                Ok((last_modified, size, hash))
+            };
+
+            return Err(CompareResult::DifferingContent(FileInfo::new(
+                sub_path,
+                get_file_details(&path_a, hash_a)?,
+                get_file_details(&path_b, hash_b)?,
+            )));
         }
     } else if !(path_a.is_dir() && path_b.is_dir()) {
         return Err(CompareResult::TypeMismatch(EntryTypeMismatch {
@@ -233,18 +432,22 @@ fn compare_entry(
 }
 
 fn compare_directory_contents<'a>(
-    dir_a_content: &'a HashSet<String>,
-    dir_b_content: &'a HashSet<String>,
+    dir_a_content: &'a HashSet<EntryPath>,
+    dir_b_content: &'a HashSet<EntryPath>,
     dir_a_path: &'a str,
     dir_b_path: &'a str,
+    options: &'a CompareOptions,
+    hash_file: &'a dyn Fn(&Path) -> Result<String, io::Error>,
 ) -> impl Iterator<Item = CompareResult> + 'a {
     let present_in_both = dir_a_content.intersection(dir_b_content);
-    present_in_both.filter_map(|path| compare_entry(dir_a_path, dir_b_path, path.to_string()).err())
+    present_in_both.filter_map(move |path| {
+        compare_entry_with(dir_a_path, dir_b_path, path.clone(), options, hash_file).err()
+    })
 }
 
 fn find_missing_entries<'a>(
-    dir_a_content: &'a HashSet<String>,
-    dir_b_content: &'a HashSet<String>,
+    dir_a_content: &'a HashSet<EntryPath>,
+    dir_b_content: &'a HashSet<EntryPath>,
 ) -> Box<dyn Iterator<Item = CompareResult> + 'a> {
     let missing_in_dir_a = remove_subdirectories(dir_b_content.difference(dir_a_content))
         .map(|path| CompareResult::MissingInDirA(EntryInfo { path: path.clone() }));
@@ -255,12 +458,32 @@ fn find_missing_entries<'a>(
     Box::new(missing_in_dir_a.chain(missing_in_dir_b))
 }
 
-#[tauri::command]
-fn compare(path_a: String, path_b: String) -> Vec<CompareResult> {
-    println!("received2");
+/// Compare everything at and below `sub_path` in both directories.
+/// An empty `sub_path` compares the whole directories.
+fn compare_sub_path(
+    path_a: &str,
+    path_b: &str,
+    sub_path: &EntryPath,
+    options: &CompareOptions,
+) -> Vec<CompareResult> {
+    compare_sub_path_with(path_a, path_b, sub_path, options, &|path| {
+        get_file_content_hash(path)
+    })
+}
 
-    let (dir_a_content, dir_a_errors) = get_directory_content_recursively(&path_a);
-    let (dir_b_content, dir_b_errors) = get_directory_content_recursively(&path_b);
+/// Like `compare_sub_path()` but calculates the content hashes of files with `hash_file`
+fn compare_sub_path_with(
+    path_a: &str,
+    path_b: &str,
+    sub_path: &EntryPath,
+    options: &CompareOptions,
+    hash_file: &dyn Fn(&Path) -> Result<String, io::Error>,
+) -> Vec<CompareResult> {
+    let ignore = options.ignore_set();
+    let (dir_a_content, dir_a_errors) =
+        get_sub_directory_content_recursively(path_a, sub_path, &ignore);
+    let (dir_b_content, dir_b_errors) =
+        get_sub_directory_content_recursively(path_b, sub_path, &ignore);
 
     let mut res = vec![]
         .into_iter()
@@ -270,8 +493,10 @@ fn compare(path_a: String, path_b: String) -> Vec<CompareResult> {
         .chain(compare_directory_contents(
             &dir_a_content,
             &dir_b_content,
-            &path_a,
-            &path_b,
+            path_a,
+            path_b,
+            options,
+            hash_file,
         ))
         .collect::<Vec<CompareResult>>();
 
@@ -283,39 +508,95 @@ fn compare(path_a: String, path_b: String) -> Vec<CompareResult> {
 }
 
 #[tauri::command]
-fn copy(source_path: String, target_path: String, sub_paths: Vec<String>) -> Vec<ErrorInfo> {
-    dbg!(&source_path, &target_path, &sub_paths);
-    let mut options = fs_extra::dir::CopyOptions::new();
-    options.overwrite = true;
+fn compare(
+    path_a: String,
+    path_b: String,
+    strategy: Option<CompareStrategy>,
+    options: Option<CompareOptions>,
+) -> Vec<CompareResult> {
+    let options = options.unwrap_or_default();
+    match strategy.unwrap_or_default() {
+        CompareStrategy::InMemory => {
+            compare_sub_path(&path_a, &path_b, &EntryPath::default(), &options)
+        }
+        CompareStrategy::MergeWalk => {
+            let mut res: Vec<CompareResult> =
+                merge_walk::MergeWalk::new(&path_a, &path_b, &options).collect();
+            // Same order as with `InMemory`
+            res.sort();
+            res
+        }
+        CompareStrategy::Merkle => merkle::compare(&path_a, &path_b, &options),
+    }
+}
+
+#[tauri::command]
+fn copy(
+    source_path: String,
+    target_path: String,
+    sub_paths: Vec<EntryPath>,
+    options: Option<copy::CopyOptions>,
+) -> Vec<ErrorInfo> {
+    let options = options.unwrap_or_default();
     sub_paths
-        .into_iter()
-        .filter_map(|path| {
-            fs_extra::copy_items(
-                &[Path::new(&source_path).join(&path)],
-                &Path::new(&target_path),
+        .iter()
+        .flat_map(|path| {
+            copy::copy_entry(
+                Path::new(&source_path),
+                Path::new(&target_path),
+                path,
                 &options,
             )
-            .map_err(|error| ErrorInfo {
-                message: error.to_string(),
-                path,
-            })
-            .err()
         })
         .collect()
 }
 
+/// Path of `name` in the app's config directory, which is created if necessary
+fn app_file(app_handle: &tauri::AppHandle, name: &str) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path_resolver()
+        .app_dir()
+        .ok_or_else(|| "Could not determine the app directory".to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    Ok(dir.join(name))
+}
+
 fn main() {
     tauri::Builder::default()
         .manage(disk_space::ShouldAbort(AtomicBool::new(false)))
+        .manage(watch::ActiveWatch(std::sync::Mutex::new(None)))
         .manage(SavedAnalysisResult(std::sync::Arc::new(
             std::sync::Mutex::new(None),
         )))
         .invoke_handler(tauri::generate_handler![
             compare,
             copy,
+            resolve::plan_resolutions,
+            resolve::apply_resolutions,
+            merkle::compare_merkle,
+            snapshot::save_comparison_snapshot,
+            snapshot::load_comparison_snapshot,
+            snapshot::diff_comparison_snapshot,
+            watch::start_watch,
+            watch::stop_watch,
             disk_space::analyze_disk_usage,
             disk_space::abort,
             disk_space::load_nested_directory,
+            disk_space::rescan_subtree,
+            scan_file::save_disk_usage,
+            scan_file::load_disk_usage,
+            scan_diff::diff_disk_usage,
+            duplicates::find_duplicates,
+            dedupe::dedupe,
+            dedupe::list_replacements,
+            dedupe::undo_replacements,
+            profiles::list_profiles,
+            profiles::create_profile,
+            profiles::update_profile,
+            profiles::delete_profile,
+            profiles::run_profile,
+            profiles::list_recent_pairs,
+            profiles::remember_recent_pair,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -327,6 +608,10 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    fn get_directory_content_recursively(dir: &str) -> (HashSet<EntryPath>, Vec<CompareResult>) {
+        get_sub_directory_content_recursively(dir, &EntryPath::default(), &GlobSet::empty())
+    }
+
     fn call_structure_compare(path: &str) -> Vec<CompareResult> {
         let (dir_content_a, dir_a_errors) =
             get_directory_content_recursively(&("./test/".to_string() + path + "/dirA"));
@@ -343,7 +628,15 @@ mod tests {
         let (dir_content_b, dir_b_errors) = get_directory_content_recursively(&path_b);
         assert_eq!(dir_a_errors, vec![]);
         assert_eq!(dir_b_errors, vec![]);
-        compare_directory_contents(&dir_content_a, &dir_content_b, &path_a, &path_b).collect()
+        compare_directory_contents(
+            &dir_content_a,
+            &dir_content_b,
+            &path_a,
+            &path_b,
+            &CompareOptions::default(),
+            &|path| get_file_content_hash(path),
+        )
+        .collect()
     }
 
     /// Creates a temporary directory under /tmp and copies the given `path` to this directory
@@ -361,7 +654,7 @@ mod tests {
             (
                 HashSet::new(),
                 vec![CompareResult::CouldNotReadDirectory(ErrorInfo {
-                    path: String::from("i_do_not_exist"),
+                    path: EntryPath::from("i_do_not_exist"),
                     message: String::from(
                         "IO error for operation on i_do_not_exist: No such file or directory (os error 2)"
                     )
@@ -374,12 +667,16 @@ mod tests {
     fn hash_invalid_file() {
         // Use /etc/sudoers to test a file we are not allowed to read
         let dir = String::from("/etc/sudoers");
-        let dir_content = &HashSet::from([dir.clone()]);
-        let results = compare_directory_contents(dir_content, dir_content, &dir, &dir);
+        let dir_content = &HashSet::from([EntryPath::from(dir.as_str())]);
+        let options = CompareOptions::default();
+        let results =
+            compare_directory_contents(dir_content, dir_content, &dir, &dir, &options, &|path| {
+                get_file_content_hash(path)
+            });
         assert_eq!(
             results.collect::<Vec<CompareResult>>(),
             vec![CompareResult::CouldNotCalculateHash(ErrorInfo {
-                path: String::from("/etc/sudoers"),
+                path: EntryPath::from("/etc/sudoers"),
                 message: String::from("Permission denied (os error 13)")
             })]
         );
@@ -395,7 +692,7 @@ mod tests {
         assert_eq!(
             call_structure_compare("02_dirA_lacks_file"),
             vec![CompareResult::MissingInDirA(EntryInfo {
-                path: String::from("file2.txt")
+                path: EntryPath::from("file2.txt")
             })]
         );
     }
@@ -405,7 +702,7 @@ mod tests {
         assert_eq!(
             call_structure_compare("03_dirB_lacks_file"),
             vec![CompareResult::MissingInDirB(EntryInfo {
-                path: String::from("file1.txt")
+                path: EntryPath::from("file1.txt")
             })]
         );
     }
@@ -415,7 +712,7 @@ mod tests {
         assert_eq!(
             call_structure_compare("04_dirA_lacks_sub_directory"),
             vec![CompareResult::MissingInDirA(EntryInfo {
-                path: String::from("subdir2")
+                path: EntryPath::from("subdir2")
             })]
         );
     }
@@ -425,19 +722,61 @@ mod tests {
         assert_eq!(
             call_structure_compare("05_dirA_lacks_file_in_sub_directory"),
             vec![CompareResult::MissingInDirA(EntryInfo {
-                path: String::from("subdir2/file2.txt")
+                path: EntryPath::from("subdir2/file2.txt")
             })]
         );
     }
 
+    #[test]
+    fn compare_sub_path_missing_in_dir_a() {
+        let path_a = "./test/04_dirA_lacks_sub_directory/dirA";
+        let path_b = "./test/04_dirA_lacks_sub_directory/dirB";
+        assert_eq!(
+            compare_sub_path(
+                path_a,
+                path_b,
+                &"subdir2".into(),
+                &CompareOptions::default()
+            ),
+            vec![CompareResult::MissingInDirA(EntryInfo {
+                path: EntryPath::from("subdir2")
+            })]
+        );
+        assert_eq!(
+            compare_sub_path(
+                path_a,
+                path_b,
+                &"subdir1".into(),
+                &CompareOptions::default()
+            ),
+            vec![]
+        );
+        assert_eq!(
+            compare_sub_path(
+                path_a,
+                path_b,
+                &"i_do_not_exist".into(),
+                &CompareOptions::default()
+            ),
+            vec![]
+        );
+    }
+
     #[test]
     fn t_06_different_text_content() {
         assert_eq!(
             call_content_compare("06_different_text_content"),
             vec![CompareResult::DifferingContent(FileInfo {
-                path: String::from("file1.txt"),
+                path: EntryPath::from("file1.txt"),
                 last_modified_in_dir_a: 1637774171,
                 last_modified_in_dir_b: 1637774802,
+                size_in_dir_a: 16,
+                size_in_dir_b: 40,
+                size_delta: 24,
+                hash_in_dir_a: "76130DD839BD6067E980CC0A564D51F64FC6C3E6A5DFCA59DEBEF4BD13EB7523"
+                    .to_string(),
+                hash_in_dir_b: "89683643B85866C26E35ACFA006BBE7557C6974FC57D2E50ECF0BDCB18C82005"
+                    .to_string(),
             })]
         );
     }
@@ -447,9 +786,16 @@ mod tests {
         assert_eq!(
             call_content_compare("07_different_binary_content"),
             vec![CompareResult::DifferingContent(FileInfo {
-                path: String::from("file1.jpeg"),
+                path: EntryPath::from("file1.jpeg"),
                 last_modified_in_dir_a: 1637774351,
                 last_modified_in_dir_b: 1637774396,
+                size_in_dir_a: 10348,
+                size_in_dir_b: 11490,
+                size_delta: 1142,
+                hash_in_dir_a: "6D2525547701AC20B352F126E8892F794C62F9D9D6BF9631654B89FCF8BE7525"
+                    .to_string(),
+                hash_in_dir_b: "CF2199280032FB5025D6C3A404E687FC9CE7AD47F9C839AB070999071A951009"
+                    .to_string(),
             })]
         );
     }
@@ -459,7 +805,7 @@ mod tests {
         assert_eq!(
             call_content_compare("08_type_mismatch"),
             vec![CompareResult::TypeMismatch(EntryTypeMismatch {
-                path: String::from("file1.txt"),
+                path: EntryPath::from("file1.txt"),
                 type_in_dir_a: EntryType::File,
                 type_in_dir_b: EntryType::Directory
             })],
@@ -480,22 +826,23 @@ mod tests {
         let path_b = base_path.join("dirB").to_string_lossy().to_string();
 
         assert_eq!(
-            compare(path_a.clone(), path_b.clone()),
+            compare(path_a.clone(), path_b.clone(), None, None),
             vec![CompareResult::MissingInDirB(EntryInfo {
-                path: "file1.txt".to_string(),
+                path: EntryPath::from("file1.txt"),
             }),]
         );
 
         let errors = copy(
             path_a.clone(),
             path_b.clone(),
-            vec!["file1.txt".to_string()],
+            vec!["file1.txt".into()],
+            None,
         );
 
         let expected_errors: Vec<ErrorInfo> = Vec::new();
         assert_eq!(errors, expected_errors);
 
-        assert_eq!(compare(path_a, path_b), vec![]);
+        assert_eq!(compare(path_a, path_b, None, None), vec![]);
 
         Ok(())
     }
@@ -508,20 +855,38 @@ mod tests {
         let path_b = base_path.join("dirB").to_string_lossy().to_string();
 
         assert_eq!(
-            compare(path_a.clone(), path_b.clone()),
+            compare(path_a.clone(), path_b.clone(), None, None),
             vec![
                 CompareResult::DifferingContent(FileInfo {
-                    path: "differing_content.txt".to_string(),
+                    path: EntryPath::from("differing_content.txt"),
                     last_modified_in_dir_a: 1638465426,
                     last_modified_in_dir_b: 1638465426,
+                    size_in_dir_a: 15,
+                    size_in_dir_b: 39,
+                    size_delta: 24,
+                    hash_in_dir_a:
+                        "C72BB8592E2299F1D1D7EF2AA940015D36380F029234943E09684606416FDAF5"
+                            .to_string(),
+                    hash_in_dir_b:
+                        "53592C05E9B5688A08FD0203C80E15BCC9ECF590E3927BC440B3E99613300EF1"
+                            .to_string(),
                 }),
                 CompareResult::DifferingContent(FileInfo {
-                    path: "differing_content2.txt".to_string(),
+                    path: EntryPath::from("differing_content2.txt"),
                     last_modified_in_dir_a: 1638465647,
                     last_modified_in_dir_b: 1638465654,
+                    size_in_dir_a: 15,
+                    size_in_dir_b: 39,
+                    size_delta: 24,
+                    hash_in_dir_a:
+                        "C72BB8592E2299F1D1D7EF2AA940015D36380F029234943E09684606416FDAF5"
+                            .to_string(),
+                    hash_in_dir_b:
+                        "53592C05E9B5688A08FD0203C80E15BCC9ECF590E3927BC440B3E99613300EF1"
+                            .to_string(),
                 }),
                 CompareResult::MissingInDirB(EntryInfo {
-                    path: "file_only_in_a.txt".to_string(),
+                    path: EntryPath::from("file_only_in_a.txt"),
                 }),
             ]
         );
@@ -530,16 +895,14 @@ mod tests {
         let errors = copy(
             path_a.clone(),
             path_b.clone(),
-            vec![
-                "file_only_in_a.txt".to_string(),
-                "differing_content.txt".to_string(),
-            ],
+            vec!["file_only_in_a.txt".into(), "differing_content.txt".into()],
+            None,
         );
 
         let expected_errors: Vec<ErrorInfo> = Vec::new();
         assert_eq!(errors, expected_errors);
 
-        let comparison_result = compare(path_a, path_b);
+        let comparison_result = compare(path_a, path_b, None, None);
         // We cannot just compare the result of compare() as the last_modified_* fields are expected
         // to be different
         assert_eq!(comparison_result.len(), 1);
@@ -555,18 +918,171 @@ mod tests {
         let path_b = base_path.join("dirB").to_string_lossy().to_string();
 
         assert_eq!(
-            compare(path_a.clone(), path_b.clone()),
+            compare(path_a.clone(), path_b.clone(), None, None),
             vec![CompareResult::MissingInDirA(EntryInfo {
-                path: String::from("subdir2")
+                path: EntryPath::from("subdir2")
+            })]
+        );
+
+        let errors = copy(path_b.clone(), path_a.clone(), vec!["subdir2".into()], None);
+
+        let expected_errors: Vec<ErrorInfo> = Vec::new();
+        assert_eq!(errors, expected_errors);
+
+        assert_eq!(compare(path_a, path_b, None, None), vec![]);
+
+        Ok(())
+    }
+    #[test]
+    fn test_copy_file_in_sub_directory() -> Result<(), fs_extra::error::Error> {
+        let dir = create_test_directory("test/05_dirA_lacks_file_in_sub_directory")?;
+        let base_path = dir.path().join("05_dirA_lacks_file_in_sub_directory");
+        let path_a = base_path.join("dirA").to_string_lossy().to_string();
+        let path_b = base_path.join("dirB").to_string_lossy().to_string();
+
+        let errors = copy(
+            path_b.clone(),
+            path_a.clone(),
+            vec!["subdir2/file2.txt".into()],
+            Some(copy::CopyOptions {
+                verify: true,
+                ..Default::default()
+            }),
+        );
+
+        let expected_errors: Vec<ErrorInfo> = Vec::new();
+        assert_eq!(errors, expected_errors);
+
+        assert_eq!(compare(path_a, path_b, None, None), vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_missing_source() -> Result<(), fs_extra::error::Error> {
+        let dir = create_test_directory("test/03_dirB_lacks_file")?;
+        let base_path = dir.path().join("03_dirB_lacks_file");
+        let path_a = base_path.join("dirA").to_string_lossy().to_string();
+        let path_b = base_path.join("dirB").to_string_lossy().to_string();
+
+        let errors = copy(
+            path_a,
+            path_b.clone(),
+            vec!["i_do_not_exist".into(), "missing/i_do_not_exist".into()],
+            None,
+        );
+
+        assert_eq!(errors.len(), 2);
+        assert!(!Path::new(&path_b).join("i_do_not_exist").exists());
+        // No directories are created for a missing source
+        assert!(!Path::new(&path_b).join("missing").exists());
+
+        Ok(())
+    }
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_symlinks() -> Result<(), fs_extra::error::Error> {
+        let dir = tempdir()?;
+        let path_a = dir.path().join("dirA");
+        let path_b = dir.path().join("dirB");
+        std::fs::create_dir_all(path_a.join("links"))?;
+        std::fs::create_dir_all(&path_b)?;
+        std::fs::write(path_a.join("file.txt"), "content")?;
+        std::os::unix::fs::symlink("../file.txt", path_a.join("links/file"))?;
+        std::os::unix::fs::symlink("..", path_a.join("links/parent"))?;
+        std::os::unix::fs::symlink("i_do_not_exist", path_a.join("links/dangling"))?;
+
+        let errors = copy(
+            path_a.to_string_lossy().to_string(),
+            path_b.to_string_lossy().to_string(),
+            vec!["links".into()],
+            None,
+        );
+
+        let expected_errors: Vec<ErrorInfo> = Vec::new();
+        assert_eq!(errors, expected_errors);
+        for (name, target) in [
+            ("file", "../file.txt"),
+            ("parent", ".."),
+            ("dangling", "i_do_not_exist"),
+        ] {
+            let link = path_b.join("links").join(name);
+            assert!(link.symlink_metadata()?.file_type().is_symlink());
+            assert_eq!(std::fs::read_link(link)?, Path::new(target));
+        }
+
+        Ok(())
+    }
+    #[test]
+    fn test_copy_preserves_timestamps() -> Result<(), fs_extra::error::Error> {
+        let dir = create_test_directory("test/04_dirA_lacks_sub_directory")?;
+        let base_path = dir.path().join("04_dirA_lacks_sub_directory");
+        let path_a = base_path.join("dirA");
+        let path_b = base_path.join("dirB");
+
+        let last_modified = filetime::FileTime::from_unix_time(1637774171, 0);
+        filetime::set_file_mtime(path_b.join("subdir2/file1.txt"), last_modified)?;
+        filetime::set_file_mtime(path_b.join("subdir2"), last_modified)?;
+
+        let errors = copy(
+            path_b.to_string_lossy().to_string(),
+            path_a.to_string_lossy().to_string(),
+            vec!["subdir2".into()],
+            Some(copy::CopyOptions {
+                preserve_timestamps: true,
+                preserve_ownership: true,
+                preserve_xattrs: true,
+                ..Default::default()
+            }),
+        );
+
+        let expected_errors: Vec<ErrorInfo> = Vec::new();
+        assert_eq!(errors, expected_errors);
+
+        assert_eq!(
+            get_last_modified_of_file(&path_a.join("subdir2/file1.txt"))?,
+            1637774171
+        );
+        assert_eq!(
+            get_last_modified_of_file(&path_a.join("subdir2"))?,
+            1637774171
+        );
+
+        Ok(())
+    }
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_non_utf8_file_name() -> Result<(), fs_extra::error::Error> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempdir()?;
+        let path_a = dir.path().join("dirA");
+        let path_b = dir.path().join("dirB");
+        let file_name = OsStr::from_bytes(b"f\xFFile.txt");
+        std::fs::create_dir_all(&path_a)?;
+        std::fs::create_dir_all(&path_b)?;
+        std::fs::write(path_a.join(file_name), "content")?;
+        let path_a = path_a.to_string_lossy().to_string();
+        let path_b = path_b.to_string_lossy().to_string();
+
+        let results = compare(path_a.clone(), path_b.clone(), None, None);
+        assert_eq!(
+            results,
+            vec![CompareResult::MissingInDirB(EntryInfo {
+                path: EntryPath::from(file_name)
             })]
         );
 
-        let errors = copy(path_b.clone(), path_a.clone(), vec!["subdir2".to_string()]);
+        // Simulate the round trip through the frontend
+        let sub_path: EntryPath =
+            serde_json::from_str(&serde_json::to_string(results[0].path()).unwrap()).unwrap();
+        let errors = copy(path_a.clone(), path_b.clone(), vec![sub_path], None);
 
         let expected_errors: Vec<ErrorInfo> = Vec::new();
         assert_eq!(errors, expected_errors);
 
-        assert_eq!(compare(path_a, path_b), vec![]);
+        assert_eq!(compare(path_a, path_b, None, None), vec![]);
 
         Ok(())
     }
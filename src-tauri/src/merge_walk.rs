@@ -0,0 +1,200 @@
+use crate::entry_path::EntryPath;
+use crate::{compare_entry, is_ignored, CompareOptions, CompareResult, EntryInfo, ErrorInfo};
+use globset::GlobSet;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// A depth first walk which visits the entries of each directory ordered by name.
+/// The order of the yielded paths is the order of `Path::cmp()`.
+struct SortedWalk {
+    root: PathBuf,
+    ignore: GlobSet,
+    iter: walkdir::IntoIter,
+}
+
+impl SortedWalk {
+    fn new(root: &str, ignore: GlobSet) -> Self {
+        SortedWalk {
+            root: PathBuf::from(root),
+            ignore,
+            iter: WalkDir::new(root)
+                .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+                .into_iter(),
+        }
+    }
+
+    /// Returns the next path relative to the root and whether it is a directory.
+    /// Errors are appended to `errors` and skipped, like ignored entries.
+    fn next(&mut self, errors: &mut VecDeque<CompareResult>) -> Option<(EntryPath, bool)> {
+        loop {
+            match self.iter.next()? {
+                Err(why) => errors.push_back(CompareResult::CouldNotReadDirectory(ErrorInfo {
+                    path: EntryPath::from(why.path().unwrap_or_else(|| Path::new(""))),
+                    message: why.to_string(),
+                })),
+                Ok(entry) if is_ignored(&self.ignore, &self.root, entry.path()) => {
+                    if entry.file_type().is_dir() {
+                        self.iter.skip_current_dir();
+                    }
+                }
+                Ok(entry) => {
+                    let sub_path = EntryPath::from(
+                        entry
+                            .path()
+                            .strip_prefix(&self.root)
+                            // This should never panic as the path should always start with the base directory
+                            .expect("Path doesn't start with base directory"),
+                    );
+                    return Some((sub_path, entry.file_type().is_dir()));
+                }
+            }
+        }
+    }
+
+    /// Don't descend into the directory which was returned last by `next()`
+    fn skip_current_dir(&mut self) {
+        self.iter.skip_current_dir();
+    }
+}
+
+/// Compares two directories by walking both at the same time.
+///
+/// Because both walks return their entries in the same order, an entry which exists in only one
+/// of the directories can be detected without keeping a list of all paths. The memory usage only
+/// depends on the depth of the directories (and the size of the directory listings), not on the
+/// number of files.
+///
+/// Subdirectories which are missing on one side or whose type differs are not descended into, so
+/// only the directory itself is reported.
+pub(crate) struct MergeWalk<'a> {
+    path_a: &'a str,
+    path_b: &'a str,
+    options: &'a CompareOptions,
+    walk_a: SortedWalk,
+    walk_b: SortedWalk,
+    next_a: Option<(EntryPath, bool)>,
+    next_b: Option<(EntryPath, bool)>,
+    pending: VecDeque<CompareResult>,
+}
+
+impl<'a> MergeWalk<'a> {
+    pub fn new(path_a: &'a str, path_b: &'a str, options: &'a CompareOptions) -> Self {
+        MergeWalk {
+            path_a,
+            path_b,
+            options,
+            walk_a: SortedWalk::new(path_a, options.ignore_set()),
+            walk_b: SortedWalk::new(path_b, options.ignore_set()),
+            next_a: None,
+            next_b: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn only_in_a(&mut self) {
+        let (path, is_dir) = self.next_a.take().unwrap();
+        if is_dir {
+            self.walk_a.skip_current_dir();
+        }
+        self.pending
+            .push_back(CompareResult::MissingInDirB(EntryInfo { path }));
+    }
+
+    fn only_in_b(&mut self) {
+        let (path, is_dir) = self.next_b.take().unwrap();
+        if is_dir {
+            self.walk_b.skip_current_dir();
+        }
+        self.pending
+            .push_back(CompareResult::MissingInDirA(EntryInfo { path }));
+    }
+
+    fn in_both(&mut self) {
+        let (path, is_dir_a) = self.next_a.take().unwrap();
+        let (_, is_dir_b) = self.next_b.take().unwrap();
+        if let Err(result) = compare_entry(self.path_a, self.path_b, path, self.options) {
+            if let CompareResult::TypeMismatch(_) = result {
+                if is_dir_a {
+                    self.walk_a.skip_current_dir();
+                }
+                if is_dir_b {
+                    self.walk_b.skip_current_dir();
+                }
+            }
+            self.pending.push_back(result);
+        }
+    }
+}
+
+impl<'a> Iterator for MergeWalk<'a> {
+    type Item = CompareResult;
+
+    fn next(&mut self) -> Option<CompareResult> {
+        loop {
+            if let Some(result) = self.pending.pop_front() {
+                return Some(result);
+            }
+
+            if self.next_a.is_none() {
+                self.next_a = self.walk_a.next(&mut self.pending);
+            }
+            if self.next_b.is_none() {
+                self.next_b = self.walk_b.next(&mut self.pending);
+            }
+
+            match (&self.next_a, &self.next_b) {
+                (None, None) => return self.pending.pop_front(),
+                (Some(_), None) => self.only_in_a(),
+                (None, Some(_)) => self.only_in_b(),
+                (Some((a, _)), Some((b, _))) => match a.as_path().cmp(b.as_path()) {
+                    std::cmp::Ordering::Less => self.only_in_a(),
+                    std::cmp::Ordering::Greater => self.only_in_b(),
+                    std::cmp::Ordering::Equal => self.in_both(),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare_sub_path;
+
+    fn compare_both_ways(path: &str) {
+        let path_a = "./test/".to_string() + path + "/dirA";
+        let path_b = "./test/".to_string() + path + "/dirB";
+        let options = CompareOptions::default();
+        let mut results: Vec<CompareResult> = MergeWalk::new(&path_a, &path_b, &options).collect();
+        results.sort();
+        assert_eq!(
+            results,
+            compare_sub_path(&path_a, &path_b, &EntryPath::default(), &options)
+        );
+    }
+
+    #[test]
+    fn same_results_as_in_memory_comparison() {
+        compare_both_ways("01_test_files_match");
+        compare_both_ways("02_dirA_lacks_file");
+        compare_both_ways("03_dirB_lacks_file");
+        compare_both_ways("04_dirA_lacks_sub_directory");
+        compare_both_ways("05_dirA_lacks_file_in_sub_directory");
+        compare_both_ways("09_3_wrong_files");
+    }
+
+    #[test]
+    fn missing_directory() {
+        let path_a = "i_do_not_exist";
+        let path_b = "./test/01_test_files_match/dirA";
+        let options = CompareOptions::default();
+        let mut results: Vec<CompareResult> = MergeWalk::new(path_a, path_b, &options).collect();
+        results.sort();
+        assert_eq!(
+            results,
+            compare_sub_path(path_a, path_b, &EntryPath::default(), &options)
+        );
+        assert_eq!(results.len(), 2);
+    }
+}
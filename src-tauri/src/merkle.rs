@@ -0,0 +1,585 @@
+use crate::entry_path::{self, EntryPath};
+use crate::{
+    compare_file_content, get_file_content_hash, CompareOptions, CompareResult, EntryInfo,
+    EntryType, EntryTypeMismatch, ErrorInfo, FileInfo,
+};
+use data_encoding::HEXUPPER;
+use filetime::FileTime;
+use globset::GlobSet;
+use ring::digest::{Context, SHA256};
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Increase whenever the format of `HashIndex` changes in an incompatible way
+const INDEX_VERSION: u32 = 1;
+
+/// A file or directory together with a hash of its content.
+///
+/// The hash of a directory is built from the names, types and hashes of its children, so two
+/// directories with the same hash have the same content, recursively.
+struct HashNode {
+    name: OsString,
+    entry_type: EntryType,
+    last_modified: u64, // seconds since UNIX_EPOCH
+    size: u64,
+    /// `Err` if the entry or one of its children could not be read
+    hash: Result<String, String>,
+    children: Vec<HashNode>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+struct IndexRecord {
+    path: EntryPath,
+    size: u64,
+    modified_seconds: i64,
+    modified_nanoseconds: u32,
+    hash: String,
+}
+
+/// Content hashes of files from a previous run. A file whose size and modification time didn't
+/// change is not read again.
+#[derive(Debug, Default)]
+pub struct HashIndex {
+    records: HashMap<EntryPath, IndexRecord>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedIndex {
+    version: u32,
+    records: Vec<IndexRecord>,
+}
+
+impl HashIndex {
+    pub fn read(file: &Path) -> Result<Self, io::Error> {
+        let reader = BufReader::new(File::open(file)?);
+        let index: SerializedIndex = serde_json::from_reader(reader)?;
+        if index.version != INDEX_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported index version {} (expected {})",
+                    index.version, INDEX_VERSION
+                ),
+            ));
+        }
+        Ok(HashIndex {
+            records: index
+                .records
+                .into_iter()
+                .map(|record| (record.path.clone(), record))
+                .collect(),
+        })
+    }
+
+    pub fn write(&self, file: &Path) -> Result<(), io::Error> {
+        let writer = BufWriter::new(File::create(file)?);
+        let mut records: Vec<IndexRecord> = self.records.values().cloned().collect();
+        records.sort_by(|a, b| a.path.cmp(&b.path));
+        let index = SerializedIndex {
+            version: INDEX_VERSION,
+            records,
+        };
+        serde_json::to_writer(writer, &index)?;
+        Ok(())
+    }
+}
+
+struct HashContext<'a> {
+    previous_index: &'a HashIndex,
+    /// See `CompareOptions::ignore_patterns`
+    ignore: GlobSet,
+    /// Only contains the files seen in this run, so removed files are dropped from the index
+    new_index: HashIndex,
+}
+
+fn hash_file(
+    context: &mut HashContext,
+    path: &Path,
+    metadata: &fs::Metadata,
+) -> io::Result<String> {
+    let entry_path = EntryPath::from(path);
+    let modified = FileTime::from_last_modification_time(metadata);
+    let record = match context.previous_index.records.get(&entry_path) {
+        Some(record)
+            if record.size == metadata.len()
+                && record.modified_seconds == modified.unix_seconds()
+                && record.modified_nanoseconds == modified.nanoseconds() =>
+        {
+            record.clone()
+        }
+        _ => IndexRecord {
+            path: entry_path.clone(),
+            size: metadata.len(),
+            modified_seconds: modified.unix_seconds(),
+            modified_nanoseconds: modified.nanoseconds(),
+            hash: get_file_content_hash(path)?,
+        },
+    };
+    let hash = record.hash.clone();
+    context.new_index.records.insert(entry_path, record);
+    Ok(hash)
+}
+
+/// Add the raw bytes of `value` with their length in front, so names containing separators or
+/// invalid UTF-8 can't produce the same input as other names
+fn update_with_os_str(digest: &mut Context, value: &OsStr) {
+    let bytes = entry_path::to_bytes(value);
+    digest.update(&(bytes.len() as u64).to_le_bytes());
+    digest.update(&bytes);
+}
+
+fn hash_children(children: &[HashNode]) -> Result<String, String> {
+    let mut digest = Context::new(&SHA256);
+    for child in children {
+        let hash = child
+            .hash
+            .as_ref()
+            .map_err(|_| "Could not read all entries".to_string())?;
+        update_with_os_str(&mut digest, &child.name);
+        digest.update(format!("{:?}", child.entry_type).as_bytes());
+        digest.update(&[0]);
+        digest.update(hash.as_bytes());
+        digest.update(b"\n");
+    }
+    Ok(HEXUPPER.encode(digest.finish().as_ref()))
+}
+
+/// A link whose target doesn't exist is hashed by its target
+fn hash_link(path: &Path) -> io::Result<String> {
+    let target = fs::read_link(path)?;
+    let mut digest = Context::new(&SHA256);
+    digest.update(b"link\0");
+    update_with_os_str(&mut digest, target.as_os_str());
+    Ok(HEXUPPER.encode(digest.finish().as_ref()))
+}
+
+/// `sub_path` is the path of the node relative to the compared directory
+fn build_node(context: &mut HashContext, path: &Path, sub_path: &Path, name: OsString) -> HashNode {
+    let mut node = HashNode {
+        name,
+        entry_type: EntryType::Unknown,
+        last_modified: 0,
+        size: 0,
+        hash: Err(String::new()),
+        children: Vec::new(),
+    };
+
+    // Like the other strategies, links are compared by the entry they point to. Directories
+    // behind links below the compared directories are not walked, so a link to one of their
+    // ancestors can't lead to endless recursion. The compared directories themselves may be links.
+    let is_link = !node.name.is_empty()
+        && fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) if is_link => {
+            node.hash = hash_link(path).map_err(|why| why.to_string());
+            return node;
+        }
+        Err(why) => {
+            node.hash = Err(why.to_string());
+            return node;
+        }
+    };
+    node.last_modified = FileTime::from_last_modification_time(&metadata).unix_seconds() as u64;
+    node.size = metadata.len();
+
+    if metadata.is_file() {
+        node.entry_type = EntryType::File;
+        node.hash = hash_file(context, path, &metadata).map_err(|why| why.to_string());
+    } else if metadata.is_dir() && is_link {
+        node.entry_type = EntryType::Directory;
+        node.hash = hash_children(&node.children);
+    } else if metadata.is_dir() {
+        node.entry_type = EntryType::Directory;
+        match fs::read_dir(path) {
+            Err(why) => node.hash = Err(why.to_string()),
+            Ok(read_dir) => {
+                let mut names: Vec<OsString> = Vec::new();
+                let mut error = None;
+                for entry in read_dir {
+                    match entry {
+                        Ok(entry) if context.ignore.is_match(sub_path.join(entry.file_name())) => {}
+                        Ok(entry) => names.push(entry.file_name()),
+                        Err(why) => error = Some(why.to_string()),
+                    }
+                }
+                names.sort();
+                node.children = names
+                    .into_iter()
+                    .map(|name| build_node(context, &path.join(&name), &sub_path.join(&name), name))
+                    .collect();
+                node.hash = match error {
+                    Some(message) => Err(message),
+                    None => hash_children(&node.children),
+                };
+            }
+        }
+    } else {
+        node.hash = Ok(String::new());
+    }
+    node
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DirectorySummary {
+    path: EntryPath,
+    number_of_differences: u64,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MerkleComparison {
+    results: Vec<CompareResult>,
+    /// Every directory which contains differences, with the number of differences below it
+    differing_directories: Vec<DirectorySummary>,
+    /// Number of directories which were identical and therefore not compared further
+    identical_directories_skipped: u64,
+}
+
+struct CompareContext<'a> {
+    path_a: &'a Path,
+    path_b: &'a Path,
+    options: &'a CompareOptions,
+    comparison: MerkleComparison,
+}
+
+/// Compare two nodes with the same name and return the number of differences found
+fn compare_nodes(
+    context: &mut CompareContext,
+    a: &HashNode,
+    b: &HashNode,
+    sub_path: PathBuf,
+) -> u64 {
+    if let (Ok(hash_a), Ok(hash_b)) = (&a.hash, &b.hash) {
+        if hash_a == hash_b {
+            if a.entry_type == EntryType::Directory {
+                context.comparison.identical_directories_skipped += 1;
+            }
+            return 0;
+        }
+    }
+
+    let path = EntryPath::from(sub_path.as_path());
+    if a.entry_type != b.entry_type {
+        context
+            .comparison
+            .results
+            .push(CompareResult::TypeMismatch(EntryTypeMismatch {
+                path,
+                type_in_dir_a: a.entry_type.clone(),
+                type_in_dir_b: b.entry_type.clone(),
+            }));
+        return 1;
+    }
+
+    if a.entry_type != EntryType::Directory {
+        for (node, root) in [(a, context.path_a), (b, context.path_b)] {
+            if let Err(message) = &node.hash {
+                context
+                    .comparison
+                    .results
+                    .push(CompareResult::CouldNotCalculateHash(ErrorInfo {
+                        path: EntryPath::from(root.join(&sub_path)),
+                        message: message.clone(),
+                    }));
+                return 1;
+            }
+        }
+        // Only regular files are compared by content, other entries (like links) by their hash
+        let result = if a.entry_type == EntryType::File {
+            compare_file_content(
+                &context.path_a.join(&sub_path),
+                &context.path_b.join(&sub_path),
+                &path,
+                context.options,
+            )
+        } else {
+            None
+        }
+        .unwrap_or_else(|| {
+            CompareResult::DifferingContent(FileInfo::new(
+                path,
+                (a.last_modified, a.size, a.hash.clone().unwrap()),
+                (b.last_modified, b.size, b.hash.clone().unwrap()),
+            ))
+        });
+        context.comparison.results.push(result);
+        return 1;
+    }
+
+    let mut differences = 0;
+    // Directories which can't be read have no children, report them instead of their content
+    for (node, root) in [(a, context.path_a), (b, context.path_b)] {
+        if let Err(message) = &node.hash {
+            if node.children.is_empty() {
+                context
+                    .comparison
+                    .results
+                    .push(CompareResult::CouldNotReadDirectory(ErrorInfo {
+                        path: EntryPath::from(root.join(&sub_path)),
+                        message: message.clone(),
+                    }));
+                differences += 1;
+            }
+        }
+    }
+
+    // Children are sorted by name, so they can be merged
+    let mut children_a = a.children.iter().peekable();
+    let mut children_b = b.children.iter().peekable();
+    loop {
+        let order = match (children_a.peek(), children_b.peek()) {
+            (None, None) => break,
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (Some(child_a), Some(child_b)) => child_a.name.cmp(&child_b.name),
+        };
+        differences += match order {
+            std::cmp::Ordering::Less => {
+                let child = children_a.next().unwrap();
+                context
+                    .comparison
+                    .results
+                    .push(CompareResult::MissingInDirB(EntryInfo {
+                        path: EntryPath::from(sub_path.join(&child.name)),
+                    }));
+                1
+            }
+            std::cmp::Ordering::Greater => {
+                let child = children_b.next().unwrap();
+                context
+                    .comparison
+                    .results
+                    .push(CompareResult::MissingInDirA(EntryInfo {
+                        path: EntryPath::from(sub_path.join(&child.name)),
+                    }));
+                1
+            }
+            std::cmp::Ordering::Equal => {
+                let child_a = children_a.next().unwrap();
+                let child_b = children_b.next().unwrap();
+                compare_nodes(context, child_a, child_b, sub_path.join(&child_a.name))
+            }
+        };
+    }
+
+    if differences > 0 {
+        context
+            .comparison
+            .differing_directories
+            .push(DirectorySummary {
+                path,
+                number_of_differences: differences,
+            });
+    }
+    differences
+}
+
+/// Compare two directories by their Merkle trees, see `HashNode`.
+///
+/// Hashes of unchanged files are taken from `index`. The returned index contains the hashes of all
+/// files of both directories and can be used for the next run.
+pub fn compare_with_index(
+    path_a: &str,
+    path_b: &str,
+    index: &HashIndex,
+    options: &CompareOptions,
+) -> (MerkleComparison, HashIndex) {
+    let mut hash_context = HashContext {
+        previous_index: index,
+        ignore: options.ignore_set(),
+        new_index: HashIndex::default(),
+    };
+    let root = Path::new("");
+    let tree_a = build_node(&mut hash_context, Path::new(path_a), root, OsString::new());
+    let tree_b = build_node(&mut hash_context, Path::new(path_b), root, OsString::new());
+
+    let mut context = CompareContext {
+        path_a: Path::new(path_a),
+        path_b: Path::new(path_b),
+        options,
+        comparison: MerkleComparison {
+            results: Vec::new(),
+            differing_directories: Vec::new(),
+            identical_directories_skipped: 0,
+        },
+    };
+    compare_nodes(&mut context, &tree_a, &tree_b, PathBuf::new());
+
+    let mut comparison = context.comparison;
+    comparison.results.sort();
+    comparison
+        .differing_directories
+        .sort_by(|a, b| a.path.cmp(&b.path));
+    (comparison, hash_context.new_index)
+}
+
+pub fn compare(path_a: &str, path_b: &str, options: &CompareOptions) -> Vec<CompareResult> {
+    compare_with_index(path_a, path_b, &HashIndex::default(), options)
+        .0
+        .results
+}
+
+/// Compare both directories by their Merkle trees.
+///
+/// If `index_file` is given, the hashes stored in it are used for unchanged files and it is
+/// updated with the hashes of this run.
+#[tauri::command(async)]
+pub fn compare_merkle(
+    path_a: String,
+    path_b: String,
+    index_file: Option<String>,
+    options: Option<CompareOptions>,
+) -> Result<MerkleComparison, String> {
+    let index = match &index_file {
+        Some(file) if Path::new(file).exists() => {
+            HashIndex::read(Path::new(file)).map_err(|e| e.to_string())?
+        }
+        _ => HashIndex::default(),
+    };
+
+    let options = options.unwrap_or_default();
+    let (comparison, new_index) = compare_with_index(&path_a, &path_b, &index, &options);
+
+    if let Some(file) = index_file {
+        new_index
+            .write(Path::new(&file))
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(comparison)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare_sub_path;
+    use tempfile::tempdir;
+
+    #[test]
+    fn identical_directories() {
+        let (comparison, index) = compare_with_index(
+            "./test/01_test_files_match/dirA",
+            "./test/01_test_files_match/dirB",
+            &HashIndex::default(),
+            &CompareOptions::default(),
+        );
+        assert_eq!(comparison.results, vec![]);
+        assert_eq!(comparison.differing_directories, vec![]);
+        assert_eq!(comparison.identical_directories_skipped, 1);
+        assert_eq!(index.records.len(), 12);
+    }
+
+    #[test]
+    fn differing_directories_are_summarized() {
+        let path_a = "./test/05_dirA_lacks_file_in_sub_directory/dirA";
+        let path_b = "./test/05_dirA_lacks_file_in_sub_directory/dirB";
+        let (comparison, _) = compare_with_index(
+            path_a,
+            path_b,
+            &HashIndex::default(),
+            &CompareOptions::default(),
+        );
+
+        assert_eq!(
+            comparison.results,
+            compare_sub_path(
+                path_a,
+                path_b,
+                &EntryPath::default(),
+                &CompareOptions::default()
+            )
+        );
+        assert_eq!(
+            comparison.differing_directories,
+            vec![
+                DirectorySummary {
+                    path: EntryPath::from(""),
+                    number_of_differences: 1
+                },
+                DirectorySummary {
+                    path: EntryPath::from("subdir2"),
+                    number_of_differences: 1
+                },
+            ]
+        );
+        // subdir1 is identical in both directories
+        assert_eq!(comparison.identical_directories_skipped, 1);
+    }
+
+    #[test]
+    fn index_is_used_for_unchanged_files() {
+        let dir = tempdir().unwrap();
+        let path_a = dir.path().join("dirA");
+        let path_b = dir.path().join("dirB");
+        fs::create_dir_all(&path_a).unwrap();
+        fs::create_dir_all(&path_b).unwrap();
+        fs::write(path_a.join("file.txt"), "A").unwrap();
+        fs::write(path_b.join("file.txt"), "B").unwrap();
+        let path_a = path_a.to_string_lossy().to_string();
+        let path_b = path_b.to_string_lossy().to_string();
+
+        let (comparison, mut index) = compare_with_index(
+            &path_a,
+            &path_b,
+            &HashIndex::default(),
+            &CompareOptions::default(),
+        );
+        assert_eq!(comparison.results.len(), 1);
+
+        // Pretend both files had the same hash the last time, the index should be trusted
+        let hash = index.records[&EntryPath::from(Path::new(&path_a).join("file.txt"))]
+            .hash
+            .clone();
+        index
+            .records
+            .get_mut(&EntryPath::from(Path::new(&path_b).join("file.txt")))
+            .unwrap()
+            .hash = hash;
+
+        let index_file = dir.path().join("index.json");
+        index.write(&index_file).unwrap();
+        let index = HashIndex::read(&index_file).unwrap();
+        let (comparison, _) =
+            compare_with_index(&path_a, &path_b, &index, &CompareOptions::default());
+        assert_eq!(comparison.results, vec![]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_are_compared_like_the_other_strategies() {
+        let dir = tempdir().unwrap();
+        let path_a = dir.path().join("dirA");
+        let path_b = dir.path().join("dirB");
+        for path in [&path_a, &path_b] {
+            fs::create_dir_all(path).unwrap();
+            fs::write(path.join("real.txt"), "content").unwrap();
+        }
+        // Following these links into the directories would recurse endlessly
+        std::os::unix::fs::symlink("..", path_a.join("parent")).unwrap();
+        std::os::unix::fs::symlink(".", path_b.join("parent")).unwrap();
+        // Links are compared by the content of their targets
+        std::os::unix::fs::symlink("real.txt", path_a.join("same")).unwrap();
+        fs::write(path_b.join("same"), "content").unwrap();
+        std::os::unix::fs::symlink("real.txt", path_a.join("different")).unwrap();
+        fs::write(path_b.join("different"), "other content").unwrap();
+        let path_a = path_a.to_string_lossy();
+        let path_b = path_b.to_string_lossy();
+
+        let results = compare(&path_a, &path_b, &CompareOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path(), &EntryPath::from("different"));
+        assert!(matches!(results[0], CompareResult::DifferingContent(_)));
+        assert_eq!(
+            results,
+            compare_sub_path(
+                &path_a,
+                &path_b,
+                &EntryPath::default(),
+                &CompareOptions::default()
+            )
+        );
+    }
+}
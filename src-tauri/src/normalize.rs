@@ -0,0 +1,170 @@
+use itertools::Itertools;
+use std::fs;
+use std::path::Path;
+
+/// Files larger than this are never loaded into memory to compare their content
+const MAX_LOADED_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A difference between two text files which can be ignored on request
+#[derive(
+    Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Normalizer {
+    /// CRLF and CR are treated as LF
+    LineEndings,
+    /// Spaces and tabs at the end of a line and empty lines at the end of the file are ignored
+    TrailingWhitespace,
+    /// A byte order mark at the start of the file is ignored
+    Bom,
+    /// UTF-16 files (detected by their byte order mark) are compared with UTF-8 files
+    Encoding,
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    let chunks = bytes.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    let units = chunks.map(|unit| from_bytes([unit[0], unit[1]]));
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()
+}
+
+/// Returns `None` if the content isn't text. The byte order mark is kept as `\u{FEFF}`.
+fn decode(bytes: &[u8], with_encoding: bool) -> Option<String> {
+    if with_encoding {
+        if bytes.starts_with(&[0xFF, 0xFE]) {
+            return decode_utf16(bytes, u16::from_le_bytes);
+        }
+        if bytes.starts_with(&[0xFE, 0xFF]) {
+            return decode_utf16(bytes, u16::from_be_bytes);
+        }
+    }
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+fn normalize(bytes: &[u8], normalizers: &[Normalizer]) -> Option<String> {
+    let mut text = decode(bytes, normalizers.contains(&Normalizer::Encoding))?;
+    if normalizers.contains(&Normalizer::Bom) {
+        if let Some(stripped) = text.strip_prefix('\u{FEFF}') {
+            text = stripped.to_string();
+        }
+    }
+    if normalizers.contains(&Normalizer::LineEndings) {
+        text = text.replace("\r\n", "\n").replace('\r', "\n");
+    }
+    if normalizers.contains(&Normalizer::TrailingWhitespace) {
+        text = text
+            .split('\n')
+            .map(|line| line.trim_end_matches(&[' ', '\t'][..]))
+            .join("\n")
+            .trim_end_matches('\n')
+            .to_string();
+    }
+    Some(text)
+}
+
+/// Returns `None` if the file is too large or can't be read
+pub fn read_small_file(path: &Path) -> Option<Vec<u8>> {
+    if fs::metadata(path).ok()?.len() > MAX_LOADED_FILE_SIZE {
+        return None;
+    }
+    fs::read(path).ok()
+}
+
+/// Check whether both files are equal after applying `normalizers`.
+///
+/// Returns the normalizers which are actually needed to make them equal, or `None` if they still
+/// differ (or are not text files).
+pub fn find_equivalence(
+    path_a: &Path,
+    path_b: &Path,
+    normalizers: &[Normalizer],
+) -> Option<Vec<Normalizer>> {
+    if normalizers.is_empty() {
+        return None;
+    }
+    let content_a = read_small_file(path_a)?;
+    let content_b = read_small_file(path_b)?;
+    let is_equal = |normalizers: &[Normalizer]| match (
+        normalize(&content_a, normalizers),
+        normalize(&content_b, normalizers),
+    ) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    };
+
+    let mut needed: Vec<Normalizer> = normalizers.iter().copied().sorted().dedup().collect();
+    if !is_equal(&needed) {
+        return None;
+    }
+    // Drop every normalizer which isn't necessary to make the files equal
+    for normalizer in needed.clone() {
+        let without: Vec<Normalizer> = needed
+            .iter()
+            .copied()
+            .filter(|n| *n != normalizer)
+            .collect();
+        if is_equal(&without) {
+            needed = without;
+        }
+    }
+    Some(needed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const ALL: [Normalizer; 4] = [
+        Normalizer::LineEndings,
+        Normalizer::TrailingWhitespace,
+        Normalizer::Bom,
+        Normalizer::Encoding,
+    ];
+
+    fn equivalence(content_a: &[u8], content_b: &[u8]) -> Option<Vec<Normalizer>> {
+        let dir = tempdir().unwrap();
+        let path_a = dir.path().join("a.txt");
+        let path_b = dir.path().join("b.txt");
+        fs::write(&path_a, content_a).unwrap();
+        fs::write(&path_b, content_b).unwrap();
+        find_equivalence(&path_a, &path_b, &ALL)
+    }
+
+    #[test]
+    fn reports_only_needed_normalizers() {
+        assert_eq!(
+            equivalence(b"a\r\nb\r\n", b"a\nb\n"),
+            Some(vec![Normalizer::LineEndings])
+        );
+        assert_eq!(
+            equivalence(b"a \nb\n\n", b"a\nb"),
+            Some(vec![Normalizer::TrailingWhitespace])
+        );
+        assert_eq!(
+            equivalence(b"\xEF\xBB\xBFa\r\n", b"a\n"),
+            Some(vec![Normalizer::LineEndings, Normalizer::Bom])
+        );
+    }
+
+    #[test]
+    fn utf16_and_utf8() {
+        assert_eq!(
+            equivalence(b"\xFF\xFEa\x00b\x00", b"ab"),
+            Some(vec![Normalizer::Bom, Normalizer::Encoding])
+        );
+        assert_eq!(
+            equivalence(b"\xFE\xFF\x00a\x00b", b"\xEF\xBB\xBFab"),
+            Some(vec![Normalizer::Encoding])
+        );
+    }
+
+    #[test]
+    fn real_differences_remain() {
+        assert_eq!(equivalence(b"a\r\n", b"b\n"), None);
+        assert_eq!(equivalence(b"\xFF\x00", b"\xFE\x00"), None);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::duplicates::build_glob_set;
+use crate::resolve::{self, Resolution, ResolutionPolicy, ResolutionRule};
+use crate::{app_file, compare, CompareOptions, CompareResult, CompareStrategy};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+const CONFIG_FILE_NAME: &str = "profiles.json";
+
+/// Increase whenever the format of `ProfileConfig` changes in an incompatible way
+const CONFIG_VERSION: u32 = 1;
+
+/// Number of directory pairs kept in `ProfileConfig::recent_pairs`
+const MAX_RECENT_PAIRS: usize = 10;
+
+/// Everything needed to repeat a comparison of two directories
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Profile {
+    /// Identifies the profile, must be unique
+    name: String,
+    path_a: String,
+    path_b: String,
+    #[serde(default)]
+    strategy: CompareStrategy,
+    #[serde(default)]
+    options: CompareOptions,
+    /// Decide how differing files are resolved, see `resolve::plan()`
+    #[serde(default)]
+    sync_rules: Vec<ResolutionRule>,
+    default_policy: Option<ResolutionPolicy>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DirectoryPair {
+    path_a: String,
+    path_b: String,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+struct ProfileConfig {
+    version: u32,
+    profiles: Vec<Profile>,
+    /// Most recently compared first
+    recent_pairs: Vec<DirectoryPair>,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        ProfileConfig {
+            version: CONFIG_VERSION,
+            profiles: Vec::new(),
+            recent_pairs: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProfileRun {
+    results: Vec<CompareResult>,
+    /// Planned resolutions for all differing files according to the sync rules of the profile
+    resolutions: Vec<Resolution>,
+}
+
+fn read_config(file: &Path) -> Result<ProfileConfig, String> {
+    if !file.exists() {
+        return Ok(ProfileConfig::default());
+    }
+    let reader = BufReader::new(File::open(file).map_err(|e| e.to_string())?);
+    let config: ProfileConfig = serde_json::from_reader(reader).map_err(|e| e.to_string())?;
+    if config.version != CONFIG_VERSION {
+        return Err(format!(
+            "Unsupported profile config version {} (expected {})",
+            config.version, CONFIG_VERSION
+        ));
+    }
+    Ok(config)
+}
+
+/// The config is written to a temporary file which is renamed into place, so a crash never
+/// leaves a truncated config behind
+fn write_config(file: &Path, config: &ProfileConfig) -> Result<(), String> {
+    let write = || -> Result<(), io::Error> {
+        let dir = file.parent().unwrap_or_else(|| Path::new("."));
+        let temp_file = tempfile::Builder::new()
+            .prefix(".bumblebee-")
+            .suffix(".tmp")
+            .tempfile_in(dir)?;
+        let mut writer = BufWriter::new(temp_file.as_file());
+        serde_json::to_writer_pretty(&mut writer, config)?;
+        writer.flush()?;
+        drop(writer);
+        temp_file.as_file().sync_all()?;
+        temp_file.persist(file).map_err(|err| err.error)?;
+        Ok(())
+    };
+    write().map_err(|e| e.to_string())
+}
+
+/// Read the config, change it with `update` and write it back if `update` succeeded
+fn update_config<T>(
+    file: &Path,
+    update: impl FnOnce(&mut ProfileConfig) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut config = read_config(file)?;
+    let result = update(&mut config)?;
+    write_config(file, &config)?;
+    Ok(result)
+}
+
+fn find_profile<'a>(config: &'a mut ProfileConfig, name: &str) -> Result<&'a mut Profile, String> {
+    config
+        .profiles
+        .iter_mut()
+        .find(|p| p.name == name)
+        .ok_or_else(|| format!("Profile \"{}\" does not exist", name))
+}
+
+fn remember_pair(config: &mut ProfileConfig, path_a: String, path_b: String) {
+    let pair = DirectoryPair { path_a, path_b };
+    config.recent_pairs.retain(|p| *p != pair);
+    config.recent_pairs.insert(0, pair);
+    config.recent_pairs.truncate(MAX_RECENT_PAIRS);
+}
+
+fn run(profile: &Profile) -> Result<ProfileRun, String> {
+    // The comparison itself would silently ignore nothing
+    build_glob_set(&profile.options.ignore_patterns)?;
+    let results = compare(
+        profile.path_a.clone(),
+        profile.path_b.clone(),
+        Some(profile.strategy),
+        Some(profile.options.clone()),
+    );
+
+    let differing_paths = results
+        .iter()
+        .filter_map(|result| match result {
+            CompareResult::DifferingContent(info) => Some(info.path.clone()),
+            _ => None,
+        })
+        .collect();
+    let resolutions = resolve::plan(
+        Path::new(&profile.path_a),
+        Path::new(&profile.path_b),
+        differing_paths,
+        profile.sync_rules.clone(),
+        profile.default_policy.unwrap_or(ResolutionPolicy::Skip),
+    )?;
+
+    Ok(ProfileRun {
+        results,
+        resolutions,
+    })
+}
+
+#[tauri::command]
+pub fn list_profiles(app_handle: tauri::AppHandle) -> Result<Vec<Profile>, String> {
+    Ok(read_config(&app_file(&app_handle, CONFIG_FILE_NAME)?)?.profiles)
+}
+
+#[tauri::command]
+pub fn create_profile(app_handle: tauri::AppHandle, profile: Profile) -> Result<(), String> {
+    update_config(&app_file(&app_handle, CONFIG_FILE_NAME)?, |config| {
+        if config.profiles.iter().any(|p| p.name == profile.name) {
+            return Err(format!("Profile \"{}\" already exists", profile.name));
+        }
+        config.profiles.push(profile);
+        Ok(())
+    })
+}
+
+/// Replace the profile called `name`. The profile may be renamed.
+#[tauri::command]
+pub fn update_profile(
+    app_handle: tauri::AppHandle,
+    name: String,
+    profile: Profile,
+) -> Result<(), String> {
+    update_config(&app_file(&app_handle, CONFIG_FILE_NAME)?, |config| {
+        if profile.name != name && config.profiles.iter().any(|p| p.name == profile.name) {
+            return Err(format!("Profile \"{}\" already exists", profile.name));
+        }
+        *find_profile(config, &name)? = profile;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn delete_profile(app_handle: tauri::AppHandle, name: String) -> Result<(), String> {
+    update_config(&app_file(&app_handle, CONFIG_FILE_NAME)?, |config| {
+        find_profile(config, &name)?;
+        config.profiles.retain(|p| p.name != name);
+        Ok(())
+    })
+}
+
+/// Compare the directories of the profile called `name` with its settings
+#[tauri::command(async)]
+pub fn run_profile(app_handle: tauri::AppHandle, name: String) -> Result<ProfileRun, String> {
+    let profile = update_config(&app_file(&app_handle, CONFIG_FILE_NAME)?, |config| {
+        let profile = find_profile(config, &name)?.clone();
+        remember_pair(config, profile.path_a.clone(), profile.path_b.clone());
+        Ok(profile)
+    })?;
+    run(&profile)
+}
+
+#[tauri::command]
+pub fn list_recent_pairs(app_handle: tauri::AppHandle) -> Result<Vec<DirectoryPair>, String> {
+    Ok(read_config(&app_file(&app_handle, CONFIG_FILE_NAME)?)?.recent_pairs)
+}
+
+/// Add a pair of directories which were compared without a profile to the recent pairs
+#[tauri::command]
+pub fn remember_recent_pair(
+    app_handle: tauri::AppHandle,
+    path_a: String,
+    path_b: String,
+) -> Result<(), String> {
+    update_config(&app_file(&app_handle, CONFIG_FILE_NAME)?, |config| {
+        remember_pair(config, path_a, path_b);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry_path::EntryPath;
+    use crate::EntryInfo;
+    use tempfile::tempdir;
+
+    fn profile(name: &str) -> Profile {
+        Profile {
+            name: name.to_string(),
+            path_a: "./test/05_dirA_lacks_file_in_sub_directory/dirA".to_string(),
+            path_b: "./test/05_dirA_lacks_file_in_sub_directory/dirB".to_string(),
+            strategy: CompareStrategy::default(),
+            options: CompareOptions::default(),
+            sync_rules: Vec::new(),
+            default_policy: None,
+        }
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join(CONFIG_FILE_NAME);
+        assert_eq!(read_config(&file).unwrap(), ProfileConfig::default());
+
+        update_config(&file, |config| {
+            config.profiles.push(profile("first"));
+            remember_pair(config, "a".to_string(), "b".to_string());
+            remember_pair(config, "c".to_string(), "d".to_string());
+            remember_pair(config, "a".to_string(), "b".to_string());
+            Ok(())
+        })
+        .unwrap();
+
+        let config = read_config(&file).unwrap();
+        assert_eq!(config.profiles, vec![profile("first")]);
+        assert_eq!(
+            config.recent_pairs,
+            vec![
+                DirectoryPair {
+                    path_a: "a".to_string(),
+                    path_b: "b".to_string()
+                },
+                DirectoryPair {
+                    path_a: "c".to_string(),
+                    path_b: "d".to_string()
+                },
+            ]
+        );
+
+        let result = update_config(&file, |config| find_profile(config, "missing").map(|_| ()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn run_with_ignore_patterns() {
+        let mut profile = profile("test");
+        assert_eq!(
+            run(&profile).unwrap().results,
+            vec![CompareResult::MissingInDirA(EntryInfo {
+                path: EntryPath::from("subdir2/file2.txt")
+            })]
+        );
+
+        profile.options.ignore_patterns = vec!["subdir2".to_string()];
+        for strategy in [
+            CompareStrategy::InMemory,
+            CompareStrategy::MergeWalk,
+            CompareStrategy::Merkle,
+        ] {
+            profile.strategy = strategy;
+            assert_eq!(run(&profile).unwrap().results, vec![]);
+        }
+
+        profile.options.ignore_patterns = vec!["[".to_string()];
+        assert!(run(&profile).is_err());
+    }
+}
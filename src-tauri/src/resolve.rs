@@ -0,0 +1,343 @@
+use crate::copy::{self, CopyOptions};
+use crate::{entry_path::EntryPath, get_last_modified_of_file, ErrorInfo};
+use globset::{Glob, GlobMatcher};
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::fs::{self, metadata};
+use std::io;
+use std::path::Path;
+
+/// How a file with differing content in both directories should be resolved
+#[derive(Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum ResolutionPolicy {
+    NewerWins,
+    LargerWins,
+    AlwaysA,
+    AlwaysB,
+    KeepBoth,
+    Skip,
+}
+
+/// Apply `policy` to every path matching the glob `pattern`.
+///
+/// The pattern is matched against the path relative to the compared directories, `*` also
+/// matches `/`, so `*.txt` matches text files at any depth.
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ResolutionRule {
+    pub pattern: String,
+    pub policy: ResolutionPolicy,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum ResolutionAction {
+    CopyAToB,
+    CopyBToA,
+    /// Keep both versions in both directories under suffixed names, see `keep_both_path()`
+    KeepBoth,
+    Skip,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Resolution {
+    pub path: EntryPath,
+    pub action: ResolutionAction,
+    pub policy: ResolutionPolicy,
+    /// The rule which decided the action, `None` if no rule matched and the default policy was used
+    pub rule: Option<ResolutionRule>,
+    /// Human readable explanation of the decision
+    pub reason: String,
+}
+
+struct CompiledRule {
+    rule: ResolutionRule,
+    matcher: GlobMatcher,
+}
+
+fn compile_rules(rules: Vec<ResolutionRule>) -> Result<Vec<CompiledRule>, String> {
+    rules
+        .into_iter()
+        .map(|rule| {
+            let matcher = Glob::new(&rule.pattern)
+                .map_err(|err| err.to_string())?
+                .compile_matcher();
+            Ok(CompiledRule { rule, matcher })
+        })
+        .collect()
+}
+
+fn decide_by_ordering(ordering: Ordering, property: &str) -> (ResolutionAction, String) {
+    match ordering {
+        Ordering::Greater => (
+            ResolutionAction::CopyAToB,
+            format!("{} in directory A", property),
+        ),
+        Ordering::Less => (
+            ResolutionAction::CopyBToA,
+            format!("{} in directory B", property),
+        ),
+        Ordering::Equal => (
+            ResolutionAction::Skip,
+            format!("Neither file is {}", property.to_lowercase()),
+        ),
+    }
+}
+
+fn decide(
+    policy: ResolutionPolicy,
+    path_a: &Path,
+    path_b: &Path,
+) -> Result<(ResolutionAction, String), io::Error> {
+    Ok(match policy {
+        ResolutionPolicy::NewerWins => decide_by_ordering(
+            get_last_modified_of_file(path_a)?.cmp(&get_last_modified_of_file(path_b)?),
+            "Newer",
+        ),
+        ResolutionPolicy::LargerWins => decide_by_ordering(
+            metadata(path_a)?.len().cmp(&metadata(path_b)?.len()),
+            "Larger",
+        ),
+        ResolutionPolicy::AlwaysA => (ResolutionAction::CopyAToB, "Always A".to_string()),
+        ResolutionPolicy::AlwaysB => (ResolutionAction::CopyBToA, "Always B".to_string()),
+        ResolutionPolicy::KeepBoth => (ResolutionAction::KeepBoth, "Keep both".to_string()),
+        ResolutionPolicy::Skip => (ResolutionAction::Skip, "Skipped".to_string()),
+    })
+}
+
+/// Decide for each of `sub_paths` how it should be resolved.
+///
+/// The first rule whose pattern matches decides, `default_policy` is used if no rule matches.
+pub fn plan(
+    path_a: &Path,
+    path_b: &Path,
+    sub_paths: Vec<EntryPath>,
+    rules: Vec<ResolutionRule>,
+    default_policy: ResolutionPolicy,
+) -> Result<Vec<Resolution>, String> {
+    let rules = compile_rules(rules)?;
+
+    Ok(sub_paths
+        .into_iter()
+        .map(|path| {
+            let rule = rules.iter().find(|r| r.matcher.is_match(&path));
+            let policy = rule.map_or(default_policy, |r| r.rule.policy);
+            let (action, reason) = decide(policy, &path_a.join(&path), &path_b.join(&path))
+                .unwrap_or_else(|why| {
+                    (
+                        ResolutionAction::Skip,
+                        format!("Could not read metadata: {}", why),
+                    )
+                });
+            Resolution {
+                path,
+                action,
+                policy,
+                rule: rule.map(|r| r.rule.clone()),
+                reason,
+            }
+        })
+        .collect())
+}
+
+/// Returns `sub_path` with a suffix naming the directory the file was copied from, for example
+/// `docs/report.txt` becomes `docs/report.from-a.txt`
+fn keep_both_path(sub_path: &EntryPath, suffix: &str) -> EntryPath {
+    let path = sub_path.as_path();
+    let mut file_name = OsString::from(path.file_stem().unwrap_or_default());
+    file_name.push(".");
+    file_name.push(suffix);
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    EntryPath::from(path.with_file_name(file_name))
+}
+
+pub fn apply(
+    path_a: &Path,
+    path_b: &Path,
+    resolution: &Resolution,
+    options: &CopyOptions,
+) -> Vec<ErrorInfo> {
+    let sub_path = &resolution.path;
+    match resolution.action {
+        ResolutionAction::CopyAToB => copy::copy_entry(path_a, path_b, sub_path, options),
+        ResolutionAction::CopyBToA => copy::copy_entry(path_b, path_a, sub_path, options),
+        ResolutionAction::KeepBoth => {
+            let from_a = keep_both_path(sub_path, "from-a");
+            let from_b = keep_both_path(sub_path, "from-b");
+            let mut errors =
+                copy::copy_entry_to(&path_a.join(sub_path), &path_b.join(&from_a), options);
+            errors.extend(copy::copy_entry_to(
+                &path_b.join(sub_path),
+                &path_a.join(&from_b),
+                options,
+            ));
+            if !errors.is_empty() {
+                return errors;
+            }
+            // Move the originals aside so that both directories contain the same files
+            for (dir, target) in [(path_a, &from_a), (path_b, &from_b)] {
+                if let Err(err) = fs::rename(dir.join(sub_path), dir.join(target)) {
+                    errors.push(ErrorInfo {
+                        path: EntryPath::from(dir.join(sub_path)),
+                        message: err.to_string(),
+                    });
+                }
+            }
+            errors
+        }
+        ResolutionAction::Skip => vec![],
+    }
+}
+
+#[tauri::command]
+pub fn plan_resolutions(
+    path_a: String,
+    path_b: String,
+    sub_paths: Vec<EntryPath>,
+    rules: Vec<ResolutionRule>,
+    default_policy: Option<ResolutionPolicy>,
+) -> Result<Vec<Resolution>, String> {
+    plan(
+        Path::new(&path_a),
+        Path::new(&path_b),
+        sub_paths,
+        rules,
+        default_policy.unwrap_or(ResolutionPolicy::Skip),
+    )
+}
+
+#[tauri::command]
+pub fn apply_resolutions(
+    path_a: String,
+    path_b: String,
+    resolutions: Vec<Resolution>,
+    options: Option<CopyOptions>,
+) -> Vec<ErrorInfo> {
+    let options = options.unwrap_or_default();
+    resolutions
+        .iter()
+        .flat_map(|resolution| apply(Path::new(&path_a), Path::new(&path_b), resolution, &options))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompareOptions;
+    use tempfile::tempdir;
+
+    fn write_file(path: &Path, content: &str, last_modified: i64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        filetime::set_file_mtime(path, filetime::FileTime::from_unix_time(last_modified, 0))
+            .unwrap();
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let dir = tempdir().unwrap();
+        let path_a = dir.path().join("dirA");
+        let path_b = dir.path().join("dirB");
+        write_file(&path_a.join("docs/a.txt"), "short", 2000);
+        write_file(&path_b.join("docs/a.txt"), "much longer", 1000);
+        write_file(&path_a.join("b.bin"), "short", 2000);
+        write_file(&path_b.join("b.bin"), "much longer", 1000);
+
+        let rules = vec![
+            ResolutionRule {
+                pattern: "docs/**".to_string(),
+                policy: ResolutionPolicy::LargerWins,
+            },
+            ResolutionRule {
+                pattern: "*.txt".to_string(),
+                policy: ResolutionPolicy::AlwaysA,
+            },
+        ];
+        let resolutions = plan(
+            &path_a,
+            &path_b,
+            vec!["docs/a.txt".into(), "b.bin".into()],
+            rules.clone(),
+            ResolutionPolicy::NewerWins,
+        )
+        .unwrap();
+
+        assert_eq!(
+            resolutions,
+            vec![
+                Resolution {
+                    path: "docs/a.txt".into(),
+                    action: ResolutionAction::CopyBToA,
+                    policy: ResolutionPolicy::LargerWins,
+                    rule: Some(rules[0].clone()),
+                    reason: "Larger in directory B".to_string(),
+                },
+                Resolution {
+                    path: "b.bin".into(),
+                    action: ResolutionAction::CopyAToB,
+                    policy: ResolutionPolicy::NewerWins,
+                    rule: None,
+                    reason: "Newer in directory A".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let rules = vec![ResolutionRule {
+            pattern: "a{b".to_string(),
+            policy: ResolutionPolicy::Skip,
+        }];
+        assert!(plan(
+            Path::new("a"),
+            Path::new("b"),
+            vec![],
+            rules,
+            ResolutionPolicy::Skip
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn keep_both() {
+        let dir = tempdir().unwrap();
+        let path_a = dir.path().join("dirA");
+        let path_b = dir.path().join("dirB");
+        write_file(&path_a.join("sub/file.txt"), "A", 1000);
+        write_file(&path_b.join("sub/file.txt"), "B", 1000);
+
+        let resolutions = plan(
+            &path_a,
+            &path_b,
+            vec!["sub/file.txt".into()],
+            vec![],
+            ResolutionPolicy::KeepBoth,
+        )
+        .unwrap();
+        let errors = apply(&path_a, &path_b, &resolutions[0], &CopyOptions::default());
+
+        assert_eq!(errors, vec![]);
+        for path in [&path_a, &path_b] {
+            assert!(!path.join("sub/file.txt").exists());
+            assert_eq!(
+                fs::read_to_string(path.join("sub/file.from-a.txt")).unwrap(),
+                "A"
+            );
+            assert_eq!(
+                fs::read_to_string(path.join("sub/file.from-b.txt")).unwrap(),
+                "B"
+            );
+        }
+        assert_eq!(
+            crate::compare_sub_path(
+                path_a.to_str().unwrap(),
+                path_b.to_str().unwrap(),
+                &EntryPath::default(),
+                &CompareOptions::default()
+            ),
+            vec![]
+        );
+    }
+}
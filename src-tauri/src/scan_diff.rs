@@ -0,0 +1,250 @@
+use crate::disk_space::SavedAnalysisResult;
+use crate::entry_path::EntryPath;
+use crate::fs_entry::FsEntry;
+use crate::scan_file::read_scan;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::mem::discriminant;
+use std::path::Path;
+
+/// Number of entries returned in `ScanDiff::largest_growth`
+const MAX_GROWTH_ENTRIES: usize = 100;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+/// How an entry changed between two scans
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EntryDiff {
+    /// Path in the newer scan, or in the older one for removed entries
+    path: EntryPath,
+    change: ChangeKind,
+    size_before: u64,
+    size_after: u64,
+    size_delta: i64,
+    number_of_files_before: u64,
+    number_of_files_after: u64,
+    number_of_files_delta: i64,
+    /// Only the entries which changed. Empty for added and removed directories.
+    content: Vec<EntryDiff>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ScanDiff {
+    root: EntryDiff,
+    /// Files and directories which grew the most, without their content
+    largest_growth: Vec<EntryDiff>,
+}
+
+/// Entries are matched by their name, so the scans may have been taken from different locations
+fn name(entry: &FsEntry) -> Option<OsString> {
+    entry
+        .path()
+        .and_then(|path| path.as_path().file_name())
+        .map(|name| name.to_os_string())
+}
+
+fn diff_entries(before: Option<&FsEntry>, after: Option<&FsEntry>) -> EntryDiff {
+    let size = |entry: Option<&FsEntry>| entry.map_or(0, |e| e.size());
+    let number_of_files = |entry: Option<&FsEntry>| entry.map_or(0, |e| e.number_of_files());
+
+    let content = match (before, after) {
+        (Some(FsEntry::Dir(before)), Some(FsEntry::Dir(after))) => {
+            let mut by_name: BTreeMap<OsString, (Option<&FsEntry>, Option<&FsEntry>)> =
+                BTreeMap::new();
+            for entry in before.content() {
+                if let Some(name) = name(entry) {
+                    by_name.entry(name).or_default().0 = Some(entry);
+                }
+            }
+            for entry in after.content() {
+                if let Some(name) = name(entry) {
+                    by_name.entry(name).or_default().1 = Some(entry);
+                }
+            }
+            by_name
+                .into_values()
+                .map(|(before, after)| diff_entries(before, after))
+                .filter(|diff| diff.change != ChangeKind::Unchanged)
+                .collect()
+        }
+        _ => vec![],
+    };
+
+    let mut diff = EntryDiff {
+        path: after
+            .or(before)
+            .and_then(|entry| entry.path())
+            .cloned()
+            .unwrap_or_default(),
+        change: ChangeKind::Unchanged,
+        size_before: size(before),
+        size_after: size(after),
+        size_delta: size(after) as i64 - size(before) as i64,
+        number_of_files_before: number_of_files(before),
+        number_of_files_after: number_of_files(after),
+        number_of_files_delta: number_of_files(after) as i64 - number_of_files(before) as i64,
+        content,
+    };
+    diff.change = match (before, after) {
+        (None, _) => ChangeKind::Added,
+        (_, None) => ChangeKind::Removed,
+        (Some(before), Some(after))
+            if discriminant(before) != discriminant(after)
+                || diff.size_delta != 0
+                || diff.number_of_files_delta != 0
+                || !diff.content.is_empty() =>
+        {
+            ChangeKind::Changed
+        }
+        _ => ChangeKind::Unchanged,
+    };
+    diff
+}
+
+/// Directories whose growth is completely explained by one of their children are left out, the
+/// child is listed instead
+fn collect_growth(diff: &EntryDiff, growth: &mut Vec<EntryDiff>) {
+    let explained_by_child = diff
+        .content
+        .iter()
+        .any(|child| child.size_delta >= diff.size_delta);
+    if diff.size_delta > 0 && !explained_by_child {
+        growth.push(EntryDiff {
+            content: vec![],
+            ..diff.clone()
+        });
+    }
+    for child in &diff.content {
+        collect_growth(child, growth);
+    }
+}
+
+/// Compare two scans of the same directory
+pub fn diff_scans(before: &FsEntry, after: &FsEntry) -> ScanDiff {
+    let root = diff_entries(Some(before), Some(after));
+    let mut largest_growth = Vec::new();
+    // The growth of the whole scan is already shown by `root`
+    for child in &root.content {
+        collect_growth(child, &mut largest_growth);
+    }
+    largest_growth.sort_by(|a, b| b.size_delta.cmp(&a.size_delta).then(a.path.cmp(&b.path)));
+    largest_growth.truncate(MAX_GROWTH_ENTRIES);
+    ScanDiff {
+        root,
+        largest_growth,
+    }
+}
+
+/// Compare the scans saved in `file` and `other_file`, the older one is used as before. If
+/// `other_file` is not given, `file` is compared with the current result of
+/// `analyze_disk_usage()` or `load_disk_usage()`.
+#[tauri::command(async)]
+pub fn diff_disk_usage(
+    saved_result: tauri::State<SavedAnalysisResult>,
+    file: String,
+    other_file: Option<String>,
+) -> Result<ScanDiff, String> {
+    let (created, scan) = read_scan(Path::new(&file)).map_err(|e| e.to_string())?;
+    match other_file {
+        Some(other_file) => {
+            let (other_created, other_scan) =
+                read_scan(Path::new(&other_file)).map_err(|e| e.to_string())?;
+            Ok(if created <= other_created {
+                diff_scans(&scan, &other_scan)
+            } else {
+                diff_scans(&other_scan, &scan)
+            })
+        }
+        None => {
+            let saved_result = saved_result.0.lock().unwrap();
+            let after = saved_result.as_ref().ok_or("There is no current scan")?;
+            Ok(diff_scans(&scan, after))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_entry::{DirEntry, FileEntry};
+
+    fn file(path: &str, size: u64) -> FsEntry {
+        FsEntry::File(FileEntry {
+            path: EntryPath::from(path),
+            size,
+            allocated_size: size,
+            links: 1,
+            already_counted: false,
+        })
+    }
+
+    fn dir(path: &str, content: Vec<FsEntry>) -> FsEntry {
+        FsEntry::Dir(DirEntry::new(EntryPath::from(path), content))
+    }
+
+    #[test]
+    fn diff_two_scans() {
+        let before = dir(
+            "/old/root",
+            vec![
+                dir("/old/root/logs", vec![file("/old/root/logs/a.log", 10)]),
+                file("/old/root/removed", 5),
+                file("/old/root/same", 1),
+                dir("/old/root/tmp", vec![file("/old/root/tmp/cache", 10)]),
+            ],
+        );
+        let after = dir(
+            "/root",
+            vec![
+                dir(
+                    "/root/logs",
+                    vec![file("/root/logs/a.log", 100), file("/root/logs/b.log", 50)],
+                ),
+                file("/root/same", 1),
+                dir("/root/tmp", vec![file("/root/tmp/cache", 30)]),
+            ],
+        );
+
+        let diff = diff_scans(&before, &after);
+        assert_eq!(diff.root.change, ChangeKind::Changed);
+        assert_eq!(diff.root.size_delta, 155);
+        assert_eq!(diff.root.number_of_files_delta, 0);
+
+        let changes: Vec<(String, ChangeKind)> = diff.root.content[0]
+            .content
+            .iter()
+            .map(|d| (d.path.display(), d.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("/root/logs/a.log".to_string(), ChangeKind::Changed),
+                ("/root/logs/b.log".to_string(), ChangeKind::Added),
+            ]
+        );
+        assert_eq!(diff.root.content[1].change, ChangeKind::Removed);
+        assert_eq!(diff.root.content.len(), 3);
+
+        let growth: Vec<(String, i64)> = diff
+            .largest_growth
+            .iter()
+            .map(|d| (d.path.display(), d.size_delta))
+            .collect();
+        assert_eq!(
+            growth,
+            vec![
+                ("/root/logs".to_string(), 140),
+                ("/root/logs/a.log".to_string(), 90),
+                ("/root/logs/b.log".to_string(), 50),
+                // "/root/tmp" only grew because of its only file
+                ("/root/tmp/cache".to_string(), 20),
+            ]
+        );
+    }
+}
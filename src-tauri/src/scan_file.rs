@@ -0,0 +1,136 @@
+use crate::disk_space::SavedAnalysisResult;
+use crate::fs_entry::FsEntry;
+use crate::snapshot::seconds_since_epoch;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+/// Increase whenever the format of `ScanFile` changes in an incompatible way
+const SCAN_FILE_VERSION: u32 = 1;
+
+/// A complete disk usage scan as written to a file, stored as gzip compressed JSON.
+/// The result is only borrowed for writing, scans can be huge.
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+struct ScanFile<'a> {
+    version: u32,
+    created: u64, // seconds since UNIX_EPOCH
+    result: Cow<'a, FsEntry>,
+}
+
+/// The file is written next to `file` and renamed into place when complete, like
+/// `copy::copy_file_atomic()` does, so an existing scan is never left half overwritten.
+fn write_scan(result: &FsEntry, file: &Path) -> Result<(), io::Error> {
+    let scan = ScanFile {
+        version: SCAN_FILE_VERSION,
+        created: seconds_since_epoch(),
+        result: Cow::Borrowed(result),
+    };
+    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+    let temp_file = tempfile::Builder::new()
+        .prefix(".bumblebee-")
+        .suffix(".tmp")
+        .tempfile_in(dir)?;
+    let mut writer = GzEncoder::new(BufWriter::new(temp_file.as_file()), Compression::default());
+    serde_json::to_writer(&mut writer, &scan)?;
+    writer
+        .finish()?
+        .into_inner()
+        .map_err(|err| err.into_error())?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(file).map_err(|err| err.error)?;
+    Ok(())
+}
+
+/// Returns when the scan was saved (seconds since UNIX_EPOCH) and the scan itself
+pub fn read_scan(file: &Path) -> Result<(u64, FsEntry), io::Error> {
+    let reader = BufReader::new(GzDecoder::new(File::open(file)?));
+    let scan: ScanFile = serde_json::from_reader(reader)?;
+    if scan.version != SCAN_FILE_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unsupported scan file version {} (expected {})",
+                scan.version, SCAN_FILE_VERSION
+            ),
+        ));
+    }
+    Ok((scan.created, scan.result.into_owned()))
+}
+
+/// Write the result of the last disk usage scan to `file`
+#[tauri::command(async)]
+pub fn save_disk_usage(
+    saved_result: tauri::State<SavedAnalysisResult>,
+    file: String,
+) -> Result<(), String> {
+    let saved_result = saved_result.0.lock().unwrap();
+    let result = saved_result.as_ref().ok_or("There is no scan to save")?;
+    write_scan(result, Path::new(&file)).map_err(|e| e.to_string())
+}
+
+/// Load a scan saved with `save_disk_usage()`, so it can be browsed with `load_nested_directory()`.
+/// Returns the top levels of the scan like `analyze_disk_usage()`.
+#[tauri::command(async)]
+pub fn load_disk_usage(
+    saved_result: tauri::State<SavedAnalysisResult>,
+    file: String,
+) -> Result<FsEntry, String> {
+    let (_, result) = read_scan(Path::new(&file)).map_err(|e| e.to_string())?;
+    let flat_result = match result {
+        FsEntry::Dir(ref d) => FsEntry::Dir(d.clone_flat(2)),
+        ref entry => entry.clone(),
+    };
+    *saved_result.0.lock().unwrap() = Some(result);
+    Ok(flat_result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry_path::EntryPath;
+    use crate::fs_entry::{DirEntry, FileEntry};
+    use tempfile::tempdir;
+
+    #[test]
+    fn scan_round_trip() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("scan.json.gz");
+        let result = FsEntry::Dir(DirEntry::new(
+            EntryPath::from("/root"),
+            vec![FsEntry::File(FileEntry {
+                path: EntryPath::from("/root/file"),
+                size: 10,
+                allocated_size: 4096,
+                links: 1,
+                already_counted: false,
+            })],
+        ));
+        write_scan(&result, &file).unwrap();
+        let (created, read_result) = read_scan(&file).unwrap();
+        assert!(created > 0);
+        assert_eq!(read_result, result);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("scan.json.gz");
+        let mut writer = GzEncoder::new(File::create(&file).unwrap(), Compression::default());
+        let scan = ScanFile {
+            version: 0,
+            created: 0,
+            result: Cow::Owned(FsEntry::Dir(DirEntry::new(
+                EntryPath::from("/root"),
+                vec![],
+            ))),
+        };
+        serde_json::to_writer(&mut writer, &scan).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            read_scan(&file).unwrap_err().to_string(),
+            "Unsupported scan file version 0 (expected 1)"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{
+    compare_sub_path_with, entry_path::EntryPath, get_entry_type, get_file_content_hash,
+    get_last_modified_of_file, is_ignored, CompareOptions, CompareResult, EntryType,
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::mem::discriminant;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// Increase whenever the format of `ComparisonSnapshot` changes in an incompatible way
+const SNAPSHOT_VERSION: u32 = 3;
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EntrySnapshot {
+    path: EntryPath,
+    entry_type: EntryType,
+    size: Option<u64>,
+    last_modified: Option<u64>, // seconds since UNIX_EPOCH
+    /// Content hash of files, `None` for directories and files which couldn't be read
+    hash: Option<String>,
+}
+
+/// The complete state of a comparison, as written to a snapshot file
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ComparisonSnapshot {
+    version: u32,
+    created: u64, // seconds since UNIX_EPOCH
+    path_a: String,
+    path_b: String,
+    options: CompareOptions,
+    entries_in_dir_a: Vec<EntrySnapshot>,
+    entries_in_dir_b: Vec<EntrySnapshot>,
+    results: Vec<CompareResult>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotDiff {
+    /// Differences which didn't exist when the snapshot was taken
+    new: Vec<CompareResult>,
+    /// Differences from the snapshot which are gone now
+    resolved: Vec<CompareResult>,
+    /// Differences which exist in the snapshot and now. The current result is returned.
+    remaining: Vec<CompareResult>,
+    /// Entries of directory A which were added, removed or modified since the snapshot
+    changed_in_dir_a: Vec<EntryPath>,
+    /// Entries of directory B which were added, removed or modified since the snapshot
+    changed_in_dir_b: Vec<EntryPath>,
+}
+
+pub fn seconds_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Clock may have gone backwards")
+        .as_secs()
+}
+
+/// Collect type, size, modification time and hash of every entry below `dir` which isn't
+/// ignored by `options`. Files hashed by the comparison are looked up in `hashes` instead of
+/// being read again. Entries which can't be read are kept with the fields we could not determine
+/// set to `None`.
+fn snapshot_directory(
+    dir: &str,
+    options: &CompareOptions,
+    hashes: &HashMap<PathBuf, String>,
+) -> Vec<EntrySnapshot> {
+    let ignore = options.ignore_set();
+    let mut entries: Vec<EntrySnapshot> = WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|entry| !is_ignored(&ignore, Path::new(dir), entry.path()))
+        .filter_map(|result| result.ok())
+        .map(|entry| {
+            let path = entry.path();
+            let entry_type = get_entry_type(path);
+            let hash = match entry_type {
+                EntryType::File => hashes
+                    .get(path)
+                    .cloned()
+                    .or_else(|| get_file_content_hash(path).ok()),
+                _ => None,
+            };
+            EntrySnapshot {
+                path: EntryPath::from(
+                    path.strip_prefix(dir)
+                        // This should never panic as the path should always start with the base directory
+                        .expect("Path doesn't start with base directory"),
+                ),
+                entry_type,
+                size: entry.metadata().ok().map(|m| m.len()),
+                last_modified: get_last_modified_of_file(path).ok(),
+                hash,
+            }
+        })
+        .collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+pub fn take_snapshot(path_a: &str, path_b: &str, options: CompareOptions) -> ComparisonSnapshot {
+    // Remember the hashes calculated by the comparison so the files are only read once
+    let hashes = RefCell::new(HashMap::new());
+    let results = compare_sub_path_with(path_a, path_b, &EntryPath::default(), &options, &|path| {
+        let hash = get_file_content_hash(path)?;
+        hashes.borrow_mut().insert(path.to_path_buf(), hash.clone());
+        Ok(hash)
+    });
+    let hashes = hashes.into_inner();
+
+    ComparisonSnapshot {
+        version: SNAPSHOT_VERSION,
+        created: seconds_since_epoch(),
+        path_a: path_a.to_string(),
+        path_b: path_b.to_string(),
+        entries_in_dir_a: snapshot_directory(path_a, &options, &hashes),
+        entries_in_dir_b: snapshot_directory(path_b, &options, &hashes),
+        options,
+        results,
+    }
+}
+
+pub fn write_snapshot(snapshot: &ComparisonSnapshot, file: &Path) -> Result<(), io::Error> {
+    let writer = BufWriter::new(File::create(file)?);
+    serde_json::to_writer(writer, snapshot)?;
+    Ok(())
+}
+
+pub fn read_snapshot(file: &Path) -> Result<ComparisonSnapshot, io::Error> {
+    let reader = BufReader::new(File::open(file)?);
+    let snapshot: ComparisonSnapshot = serde_json::from_reader(reader)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unsupported snapshot version {} (expected {})",
+                snapshot.version, SNAPSHOT_VERSION
+            ),
+        ));
+    }
+    Ok(snapshot)
+}
+
+/// Split the results into new, resolved and remaining differences.
+/// Two results describe the same difference if they have the same type and path, the details
+/// (like the modification times) may have changed in the meantime.
+fn diff_results(
+    saved: &[CompareResult],
+    current: Vec<CompareResult>,
+) -> (Vec<CompareResult>, Vec<CompareResult>, Vec<CompareResult>) {
+    let key = |r: &CompareResult| (discriminant(r), r.path().clone());
+    let saved_keys: HashSet<_> = saved.iter().map(key).collect();
+    let current_keys: HashSet<_> = current.iter().map(key).collect();
+
+    let resolved = saved
+        .iter()
+        .filter(|r| !current_keys.contains(&key(r)))
+        .cloned()
+        .collect();
+    let (remaining, new) = current
+        .into_iter()
+        .partition(|r| saved_keys.contains(&key(r)));
+
+    (new, resolved, remaining)
+}
+
+/// Paths of the entries which only exist in one of the lists or differ between them
+fn changed_entries(saved: &[EntrySnapshot], current: &[EntrySnapshot]) -> Vec<EntryPath> {
+    let saved: HashMap<_, _> = saved.iter().map(|e| (&e.path, e)).collect();
+    let current: HashMap<_, _> = current.iter().map(|e| (&e.path, e)).collect();
+    let mut changed: Vec<EntryPath> = saved
+        .keys()
+        .chain(current.keys())
+        .filter(|path| saved.get(*path) != current.get(*path))
+        .map(|path| (*path).clone())
+        .collect();
+    changed.sort();
+    changed.dedup();
+    changed
+}
+
+/// Compare a new snapshot of the same directories with the `saved` one
+pub fn diff_snapshots(saved: &ComparisonSnapshot, current: ComparisonSnapshot) -> SnapshotDiff {
+    let (new, resolved, remaining) = diff_results(&saved.results, current.results);
+    SnapshotDiff {
+        new,
+        resolved,
+        remaining,
+        changed_in_dir_a: changed_entries(&saved.entries_in_dir_a, &current.entries_in_dir_a),
+        changed_in_dir_b: changed_entries(&saved.entries_in_dir_b, &current.entries_in_dir_b),
+    }
+}
+
+/// Compare both directories and save the complete result to `file`
+#[tauri::command(async)]
+pub fn save_comparison_snapshot(
+    path_a: String,
+    path_b: String,
+    file: String,
+    options: Option<CompareOptions>,
+) -> Result<Vec<CompareResult>, String> {
+    let snapshot = take_snapshot(&path_a, &path_b, options.unwrap_or_default());
+    write_snapshot(&snapshot, Path::new(&file)).map_err(|e| e.to_string())?;
+    Ok(snapshot.results)
+}
+
+#[tauri::command]
+pub fn load_comparison_snapshot(file: String) -> Result<ComparisonSnapshot, String> {
+    read_snapshot(Path::new(&file)).map_err(|e| e.to_string())
+}
+
+/// Compare the directories of the snapshot in `file` again, with the options used for the
+/// snapshot, and return how the differences and the directories changed
+#[tauri::command(async)]
+pub fn diff_comparison_snapshot(file: String) -> Result<SnapshotDiff, String> {
+    let saved = read_snapshot(Path::new(&file)).map_err(|e| e.to_string())?;
+    let current = take_snapshot(&saved.path_a, &saved.path_b, saved.options.clone());
+    Ok(diff_snapshots(&saved, current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntryInfo, FileInfo};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn diff_against_saved_results() {
+        let saved = vec![
+            CompareResult::MissingInDirA(EntryInfo {
+                path: "resolved.txt".into(),
+            }),
+            CompareResult::DifferingContent(FileInfo::new(
+                "remaining.txt".into(),
+                (1, 10, "A".to_string()),
+                (2, 20, "B".to_string()),
+            )),
+        ];
+        let current = vec![
+            CompareResult::DifferingContent(FileInfo::new(
+                "remaining.txt".into(),
+                (3, 10, "A".to_string()),
+                (2, 20, "B".to_string()),
+            )),
+            CompareResult::MissingInDirB(EntryInfo {
+                path: "resolved.txt".into(),
+            }),
+        ];
+
+        assert_eq!(
+            diff_results(&saved, current.clone()),
+            (
+                vec![current[1].clone()],
+                vec![saved[0].clone()],
+                vec![current[0].clone()],
+            )
+        );
+    }
+
+    #[test]
+    fn write_and_read_snapshot() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("snapshot.json");
+        let snapshot = take_snapshot(
+            "./test/03_dirB_lacks_file/dirA",
+            "./test/03_dirB_lacks_file/dirB",
+            CompareOptions::default(),
+        );
+        assert_eq!(snapshot.entries_in_dir_a.len(), 3);
+        assert_eq!(snapshot.entries_in_dir_a[1].entry_type, EntryType::File);
+        assert!(snapshot.entries_in_dir_a[1].hash.is_some());
+        assert_eq!(snapshot.entries_in_dir_a[0].hash, None);
+
+        write_snapshot(&snapshot, &file).unwrap();
+        assert_eq!(read_snapshot(&file).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn diff_snapshots_reports_changed_entries() {
+        let dir = tempdir().unwrap();
+        let path_a = dir.path().join("dirA");
+        let path_b = dir.path().join("dirB");
+        for path in [&path_a, &path_b] {
+            fs::create_dir_all(path.join("ignored")).unwrap();
+            fs::write(path.join("file.txt"), "content").unwrap();
+            fs::write(path.join("ignored/file.txt"), "content").unwrap();
+        }
+        let path_a = path_a.to_str().unwrap();
+        let path_b = path_b.to_str().unwrap();
+        let options = CompareOptions {
+            ignore_patterns: vec!["ignored".to_string()],
+            ..CompareOptions::default()
+        };
+        let saved = take_snapshot(path_a, path_b, options.clone());
+        assert_eq!(saved.results, vec![]);
+        assert_eq!(saved.entries_in_dir_a.len(), 2);
+
+        // Same size and modification time, only the hash tells the content changed
+        let modified = fs::metadata(Path::new(path_b).join("file.txt"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        fs::write(Path::new(path_b).join("file.txt"), "CONTENT").unwrap();
+        filetime::set_file_mtime(
+            Path::new(path_b).join("file.txt"),
+            filetime::FileTime::from_system_time(modified),
+        )
+        .unwrap();
+        fs::write(Path::new(path_b).join("ignored/file.txt"), "changed").unwrap();
+
+        let diff = diff_snapshots(&saved, take_snapshot(path_a, path_b, options));
+        assert_eq!(diff.new.len(), 1);
+        assert_eq!(diff.new[0].path(), &EntryPath::from("file.txt"));
+        assert!(diff.changed_in_dir_a.is_empty());
+        assert_eq!(diff.changed_in_dir_b, vec![EntryPath::from("file.txt")]);
+    }
+}
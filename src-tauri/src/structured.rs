@@ -0,0 +1,155 @@
+use crate::normalize::read_small_file;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// A file format whose content can be compared as data instead of text
+#[derive(
+    Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl DataFormat {
+    /// Detect the format by the file extension
+    fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(DataFormat::Json),
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            "toml" => Some(DataFormat::Toml),
+            _ => None,
+        }
+    }
+
+    fn parse(&self, content: &[u8]) -> Option<Value> {
+        match self {
+            DataFormat::Json => serde_json::from_slice(content).ok(),
+            DataFormat::Yaml => serde_yaml::from_slice(content).ok(),
+            DataFormat::Toml => toml::from_slice(content).ok(),
+        }
+    }
+}
+
+fn is_plain_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+fn child_key_path(parent: &str, key: &str) -> String {
+    if is_plain_key(key) {
+        format!("{}.{}", parent, key)
+    } else {
+        format!("{}[{}]", parent, Value::from(key))
+    }
+}
+
+/// Collect the key paths (like `$.server.ports[1]`) of all values which differ
+fn collect_differences(a: &Value, b: &Value, key_path: String, differences: &mut Vec<String>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let child_path = child_key_path(&key_path, key);
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => collect_differences(a, b, child_path, differences),
+                    _ => differences.push(child_path),
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for index in 0..a.len().max(b.len()) {
+                let child_path = format!("{}[{}]", key_path, index);
+                match (a.get(index), b.get(index)) {
+                    (Some(a), Some(b)) => collect_differences(a, b, child_path, differences),
+                    _ => differences.push(child_path),
+                }
+            }
+        }
+        (a, b) => {
+            if a != b {
+                differences.push(key_path);
+            }
+        }
+    }
+}
+
+/// Parse both files as JSON, YAML or TOML (depending on the extension) and compare the data.
+///
+/// Returns the format and the key paths of all differing values, which is empty if both files are
+/// semantically equal. Returns `None` if the format is unknown or one of the files can't be parsed.
+pub fn compare_data(path_a: &Path, path_b: &Path) -> Option<(DataFormat, Vec<String>)> {
+    let format = DataFormat::of(path_a)?;
+    let data_a = format.parse(&read_small_file(path_a)?)?;
+    let data_b = format.parse(&read_small_file(path_b)?)?;
+
+    let mut differences = Vec::new();
+    collect_differences(&data_a, &data_b, "$".to_string(), &mut differences);
+    Some((format, differences))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn compare(file_name: &str, content_a: &str, content_b: &str) -> Option<Vec<String>> {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        fs::create_dir(dir.path().join("b")).unwrap();
+        let path_a = dir.path().join("a").join(file_name);
+        let path_b = dir.path().join("b").join(file_name);
+        fs::write(&path_a, content_a).unwrap();
+        fs::write(&path_b, content_b).unwrap();
+        compare_data(&path_a, &path_b).map(|(_, differences)| differences)
+    }
+
+    #[test]
+    fn key_order_and_formatting_are_ignored() {
+        assert_eq!(
+            compare("a.json", r#"{"a": 1, "b": [1, 2]}"#, r#"{"b":[1,2],"a":1}"#),
+            Some(vec![])
+        );
+        assert_eq!(
+            compare("a.yml", "a: 1\nb:\n  - 1\n", "b: [1]\na: 1\n"),
+            Some(vec![])
+        );
+        assert_eq!(
+            compare(
+                "a.toml",
+                "a = 1\n[b]\nc = 'x'\n",
+                "b = { c = \"x\" }\na = 1\n"
+            ),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn differing_key_paths() {
+        assert_eq!(
+            compare(
+                "a.json",
+                r#"{"server": {"port": 80, "hosts": ["a", "b"]}, "old": 1}"#,
+                r#"{"server": {"port": 8080, "hosts": ["a"]}, "new key": 1}"#
+            ),
+            Some(vec![
+                "$[\"new key\"]".to_string(),
+                "$.old".to_string(),
+                "$.server.hosts[1]".to_string(),
+                "$.server.port".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn unknown_or_invalid_files() {
+        assert_eq!(compare("a.txt", "{}", "{ }"), None);
+        assert_eq!(compare("a.json", "{", "{}"), None);
+    }
+}
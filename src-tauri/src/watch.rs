@@ -0,0 +1,256 @@
+use crate::{compare_sub_path, entry_path::EntryPath, CompareOptions, CompareResult, ErrorInfo};
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::Manager;
+
+/// The watcher of the currently watched directory pair. Dropping it stops the watch thread.
+pub struct ActiveWatch(pub Mutex<Option<RecommendedWatcher>>);
+
+/// Sent to the frontend whenever something below `path` changed.
+/// All previous results at or below `path` should be replaced by `results`.
+/// An empty `path` means that the whole comparison was redone.
+#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CompareUpdate {
+    path: EntryPath,
+    results: Vec<CompareResult>,
+}
+
+/// Returns the path of `changed` relative to one of the roots
+fn get_sub_path(roots: &[PathBuf], changed: &Path) -> Option<EntryPath> {
+    roots
+        .iter()
+        .find_map(|root| changed.strip_prefix(root).ok().map(EntryPath::from))
+}
+
+/// Returns the outermost ancestor of `sub_path` which doesn't exist in both directories.
+///
+/// If a file is created in a directory which is missing on the other side, only the directory is
+/// reported as missing (see `remove_subdirectories()`), so this directory needs to be updated
+/// instead of the file.
+fn get_path_to_update(path_a: &Path, path_b: &Path, sub_path: &EntryPath) -> EntryPath {
+    let mut current = PathBuf::new();
+    for component in sub_path.as_path().components() {
+        current.push(component);
+        if path_a.join(&current).symlink_metadata().is_err()
+            || path_b.join(&current).symlink_metadata().is_err()
+        {
+            break;
+        }
+    }
+    EntryPath::from(current)
+}
+
+/// Error results carry the absolute path of the entry which failed, all other results a path
+/// relative to the compared directories. Use the relative path for errors too, so they are replaced
+/// like every other result once the path changes. The absolute path is kept in the message.
+fn with_relative_error_path(result: CompareResult, roots: &[PathBuf]) -> CompareResult {
+    let relative = |info: ErrorInfo| match get_sub_path(roots, info.path.as_path()) {
+        Some(sub_path) => ErrorInfo {
+            message: format!("{}: {}", info.path, info.message),
+            path: sub_path,
+        },
+        None => info,
+    };
+    match result {
+        CompareResult::CouldNotReadDirectory(info) => {
+            CompareResult::CouldNotReadDirectory(relative(info))
+        }
+        CompareResult::CouldNotCalculateHash(info) => {
+            CompareResult::CouldNotCalculateHash(relative(info))
+        }
+        CompareResult::CouldNotGetLastModified(info) => {
+            CompareResult::CouldNotGetLastModified(relative(info))
+        }
+        CompareResult::CouldNotGetSize(info) => CompareResult::CouldNotGetSize(relative(info)),
+        result => result,
+    }
+}
+
+fn compare_update(
+    path_a: &str,
+    path_b: &str,
+    sub_path: EntryPath,
+    options: &CompareOptions,
+    roots: &[PathBuf],
+) -> CompareUpdate {
+    CompareUpdate {
+        results: compare_sub_path(path_a, path_b, &sub_path, options)
+            .into_iter()
+            .map(|result| with_relative_error_path(result, roots))
+            .collect(),
+        path: sub_path,
+    }
+}
+
+/// Drop every path which is contained in another path of the set
+fn remove_nested_paths(paths: BTreeSet<EntryPath>) -> Vec<EntryPath> {
+    let mut result: Vec<EntryPath> = Vec::new();
+    for path in paths {
+        let is_nested = result.iter().any(|parent| path.starts_with(parent));
+        if !is_nested {
+            result.push(path);
+        }
+    }
+    result
+}
+
+fn changed_paths(event: DebouncedEvent) -> Vec<PathBuf> {
+    match event {
+        DebouncedEvent::Create(p)
+        | DebouncedEvent::Write(p)
+        | DebouncedEvent::Chmod(p)
+        | DebouncedEvent::Remove(p) => vec![p],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        // Events were lost, the only safe thing to do is to compare everything again
+        DebouncedEvent::Rescan | DebouncedEvent::Error(_, None) => vec![PathBuf::new()],
+        DebouncedEvent::Error(_, Some(p)) => vec![p],
+        DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => vec![],
+    }
+}
+
+fn watch_loop(
+    app_handle: tauri::AppHandle,
+    receiver: Receiver<DebouncedEvent>,
+    path_a: String,
+    path_b: String,
+    options: CompareOptions,
+    roots: Vec<PathBuf>,
+) {
+    // Replace the results of the initial comparison, whose errors still have absolute paths
+    let payload = compare_update(&path_a, &path_b, EntryPath::default(), &options, &roots);
+    if let Err(err) = app_handle.emit_all("compare-update", payload) {
+        eprintln!("Could not send compare update: {}", err);
+    }
+
+    // The loop ends when the watcher (and with it the sender) is dropped
+    while let Ok(event) = receiver.recv() {
+        // Collect everything which happened in the meantime so each path is only compared once
+        let events = std::iter::once(event).chain(receiver.try_iter());
+        let sub_paths: BTreeSet<EntryPath> = events
+            .flat_map(changed_paths)
+            .filter_map(|changed| {
+                if changed.as_os_str().is_empty() {
+                    return Some(EntryPath::default());
+                }
+                let sub_path = get_sub_path(&roots, &changed)?;
+                Some(get_path_to_update(
+                    Path::new(&path_a),
+                    Path::new(&path_b),
+                    &sub_path,
+                ))
+            })
+            .collect();
+
+        for sub_path in remove_nested_paths(sub_paths) {
+            let payload = compare_update(&path_a, &path_b, sub_path, &options, &roots);
+            if let Err(err) = app_handle.emit_all("compare-update", payload) {
+                eprintln!("Could not send compare update: {}", err);
+            }
+        }
+    }
+}
+
+/// Watch both directories and emit a `compare-update` event for every change.
+/// A watch started before is stopped.
+#[tauri::command]
+pub fn start_watch(
+    app_handle: tauri::AppHandle,
+    active_watch: tauri::State<'_, ActiveWatch>,
+    path_a: String,
+    path_b: String,
+    options: Option<CompareOptions>,
+) -> Result<(), String> {
+    let (sender, receiver) = channel();
+    let mut watcher = watcher(sender, Duration::from_millis(500)).map_err(|e| e.to_string())?;
+
+    // The events contain canonicalized paths, so we need those to find the sub path
+    let mut roots = Vec::new();
+    for path in [&path_a, &path_b] {
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        roots.push(PathBuf::from(path));
+        if let Ok(canonical) = Path::new(path).canonicalize() {
+            roots.push(canonical);
+        }
+    }
+
+    *active_watch.0.lock().unwrap() = Some(watcher);
+
+    let options = options.unwrap_or_default();
+    thread::spawn(move || watch_loop(app_handle, receiver, path_a, path_b, options, roots));
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_watch(active_watch: tauri::State<'_, ActiveWatch>) {
+    *active_watch.0.lock().unwrap() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_paths_are_removed() {
+        let paths = BTreeSet::from([
+            "dir".into(),
+            "dir/file".into(),
+            "dir2".into(),
+            "dir_other/file".into(),
+        ]);
+        assert_eq!(
+            remove_nested_paths(paths),
+            vec![
+                EntryPath::from("dir"),
+                EntryPath::from("dir2"),
+                EntryPath::from("dir_other/file")
+            ]
+        );
+    }
+
+    #[test]
+    fn error_is_replaced_by_update_of_its_path() {
+        let path_a = Path::new("./test/04_dirA_lacks_sub_directory/dirA");
+        let path_b = Path::new("./test/04_dirA_lacks_sub_directory/dirB");
+        let roots = vec![path_a.to_path_buf(), path_b.to_path_buf()];
+        let error = CompareResult::CouldNotCalculateHash(ErrorInfo {
+            path: EntryPath::from(path_b.join("subdir1/file1.txt")),
+            message: "Permission denied".to_string(),
+        });
+        let error = with_relative_error_path(error, &roots);
+        assert_eq!(error.path(), &EntryPath::from("subdir1/file1.txt"));
+
+        // Once the file becomes readable, the update for it replaces the error
+        let update_path = get_path_to_update(path_a, path_b, &"subdir1/file1.txt".into());
+        assert!(error.path().starts_with(&update_path));
+        let update = compare_update(
+            &path_a.to_string_lossy(),
+            &path_b.to_string_lossy(),
+            update_path,
+            &CompareOptions::default(),
+            &roots,
+        );
+        assert_eq!(update.results, vec![]);
+    }
+
+    #[test]
+    fn update_missing_parent_directory() {
+        let path_a = Path::new("./test/04_dirA_lacks_sub_directory/dirA");
+        let path_b = Path::new("./test/04_dirA_lacks_sub_directory/dirB");
+        assert_eq!(
+            get_path_to_update(path_a, path_b, &"subdir2/file1.txt".into()),
+            "subdir2"
+        );
+        assert_eq!(
+            get_path_to_update(path_a, path_b, &"subdir1/file1.txt".into()),
+            "subdir1/file1.txt"
+        );
+    }
+}
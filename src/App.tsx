@@ -3,6 +3,7 @@ import { Layout, Menu } from 'antd';
 import './App.css';
 import CompareScreen from './CompareScreen';
 import DiskSpaceScreen from './DiskSpaceScreen';
+import DuplicatesScreen from './DuplicatesScreen';
 
 const { Header, Content, Footer, Sider } = Layout;
 
@@ -24,6 +25,7 @@ function App() {
         >
           <Menu.Item key='compare'>Compare Directories</Menu.Item>
           <Menu.Item key='diskSpace'>Analyze disk space</Menu.Item>
+          <Menu.Item key='duplicates'>Find duplicates</Menu.Item>
         </Menu>
       </Sider>
       <Layout className='site-layout'>
@@ -32,6 +34,7 @@ function App() {
           <div className='site-layout-background' style={{ padding: 24, minHeight: 360 }}>
             {activeScren === 'compare' ? <CompareScreen /> : null}
             {activeScren === 'diskSpace' ? <DiskSpaceScreen /> : null}
+            {activeScren === 'duplicates' ? <DuplicatesScreen /> : null}
           </div>
         </Content>
         <Footer style={{ textAlign: 'center' }}>XAnt Design ©2018 Created by Ant UED</Footer>
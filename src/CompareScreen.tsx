@@ -1,12 +1,23 @@
-import { useState } from 'react';
+import { useEffect, useState } from 'react';
 import { Button } from 'antd';
 import { DoubleRightOutlined, DoubleLeftOutlined } from '@ant-design/icons';
 import './App.css';
 import { invoke } from '@tauri-apps/api/tauri';
+import { listen } from '@tauri-apps/api/event';
 
 import CopyModal, { useModalState } from './CopyModal';
 import ComparisonTable, { useTableState } from './ComparisonTable';
-import { CompareResult } from './types';
+import {
+  CompareOptions,
+  CompareResult,
+  CompareStrategy,
+  DirectoryPair,
+  EntryPath,
+  Profile,
+  ProfileRun,
+  Resolution,
+  displayPath,
+} from './types';
 import DirectorySelect from './DirectorySelect';
 
 const CompareScreen = () => {
@@ -14,9 +25,173 @@ const CompareScreen = () => {
   const [pathB, setPathB] = useState<string>('');
   const tableApi = useTableState();
   const modalApi = useModalState();
+  const [isWatching, setIsWatching] = useState(false);
+  const [strategy, setStrategy] = useState<CompareStrategy>('InMemory');
+  const [normalizeText, setNormalizeText] = useState(false);
+  const [compareStructuredData, setCompareStructuredData] = useState(false);
+  const [ignoreImageMetadata, setIgnoreImageMetadata] = useState(false);
+  const [ignorePatterns, setIgnorePatterns] = useState('');
+  const options: CompareOptions = {
+    normalizers: normalizeText ? ['LineEndings', 'TrailingWhitespace', 'Bom', 'Encoding'] : [],
+    compare_structured_data: compareStructuredData,
+    ignore_image_metadata: ignoreImageMetadata,
+    ignore_patterns: ignorePatterns
+      .split(',')
+      .map((p) => p.trim())
+      .filter(Boolean),
+  };
+  const [profiles, setProfiles] = useState<Profile[]>([]);
+  const [profileName, setProfileName] = useState('');
+  const [recentPairs, setRecentPairs] = useState<DirectoryPair[]>([]);
+  const [resolutions, setResolutions] = useState<Resolution[]>([]);
+  const { updateComparisonResult } = tableApi;
+
+  const loadProfiles = () => {
+    invoke<Profile[]>('list_profiles').then(setProfiles).catch(console.error);
+    invoke<DirectoryPair[]>('list_recent_pairs').then(setRecentPairs).catch(console.error);
+  };
+
+  const selectedProfile = profiles.find((p) => p.name === profileName);
+
+  // Sync rules are kept from the stored profile, they can't be edited here yet
+  const currentProfile = (): Profile => ({
+    name: profileName,
+    path_a: pathA,
+    path_b: pathB,
+    strategy,
+    options,
+    sync_rules: selectedProfile?.sync_rules ?? [],
+    default_policy: selectedProfile?.default_policy ?? null,
+  });
+
+  const applyProfile = (profile: Profile) => {
+    setProfileName(profile.name);
+    setPathA(profile.path_a);
+    setPathB(profile.path_b);
+    setIgnorePatterns((profile.options.ignore_patterns ?? []).join(', '));
+    setStrategy(profile.strategy);
+    setNormalizeText(!!profile.options.normalizers?.length);
+    setCompareStructuredData(!!profile.options.compare_structured_data);
+    setIgnoreImageMetadata(!!profile.options.ignore_image_metadata);
+  };
+
+  useEffect(() => {
+    loadProfiles();
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, []);
+
+  useEffect(() => {
+    if (!isWatching) {
+      return;
+    }
+    const unlisten = listen<{ path: EntryPath; results: CompareResult }>('compare-update', (event) =>
+      updateComparisonResult(event.payload.path, event.payload.results)
+    );
+    invoke('start_watch', { pathA, pathB, options }).catch((e) => {
+      console.error(e);
+      setIsWatching(false);
+    });
+
+    return () => {
+      invoke('stop_watch').catch(console.error);
+      unlisten.then((f) => f());
+    };
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [
+    isWatching,
+    pathA,
+    pathB,
+    normalizeText,
+    compareStructuredData,
+    ignoreImageMetadata,
+    ignorePatterns,
+  ]);
 
   return (
     <>
+      <div style={{ marginBottom: 10 }}>
+        <label>
+          Profile:{' '}
+          <select
+            value={selectedProfile ? profileName : ''}
+            onChange={(e) => {
+              const profile = profiles.find((p) => p.name === e.target.value);
+              if (profile) {
+                applyProfile(profile);
+              }
+            }}
+          >
+            <option value=''>-</option>
+            {profiles.map((p) => (
+              <option key={p.name} value={p.name}>
+                {p.name}
+              </option>
+            ))}
+          </select>
+        </label>
+        <input
+          value={profileName}
+          placeholder='Profile name'
+          onChange={(e) => setProfileName(e.target.value)}
+          style={{ marginLeft: 10 }}
+        />
+        <button
+          disabled={!profileName}
+          onClick={() =>
+            invoke(selectedProfile ? 'update_profile' : 'create_profile', {
+              name: profileName,
+              profile: currentProfile(),
+            })
+              .then(loadProfiles)
+              .catch(console.error)
+          }
+        >
+          Save profile
+        </button>
+        <button
+          disabled={!selectedProfile}
+          onClick={() =>
+            invoke('delete_profile', { name: profileName }).then(loadProfiles).catch(console.error)
+          }
+        >
+          Delete profile
+        </button>
+        <button
+          disabled={!selectedProfile}
+          onClick={() =>
+            invoke<ProfileRun>('run_profile', { name: profileName })
+              .then((run) => {
+                tableApi.setComparisonResult(run.results);
+                setResolutions(run.resolutions);
+              })
+              .then(loadProfiles)
+              .catch(console.error)
+          }
+        >
+          Run profile
+        </button>
+        <label style={{ marginLeft: 10 }}>
+          Recent:{' '}
+          <select
+            value=''
+            onChange={(e) => {
+              const pair = recentPairs[parseInt(e.target.value)];
+              if (pair) {
+                setPathA(pair.path_a);
+                setPathB(pair.path_b);
+              }
+            }}
+          >
+            <option value=''>-</option>
+            {recentPairs.map((pair, i) => (
+              <option key={i} value={i}>
+                {pair.path_a} ↔ {pair.path_b}
+              </option>
+            ))}
+          </select>
+        </label>
+      </div>
+
       <DirectorySelect value={pathA} onChange={setPathA} buttonLabel='Set directory A' />
       <DirectorySelect value={pathB} onChange={setPathB} buttonLabel='Set directory B' />
 
@@ -25,15 +200,71 @@ const CompareScreen = () => {
           onClick={() => {
             console.log('invoke');
 
-            invoke<CompareResult>('compare', { pathA, pathB })
+            invoke<CompareResult>('compare', {
+              pathA,
+              pathB,
+              strategy,
+              options,
+            })
               .then((message) => {
                 tableApi.setComparisonResult(message);
+                setResolutions([]);
               })
               .catch((e) => console.error(e));
+            invoke('remember_recent_pair', { pathA, pathB })
+              .then(loadProfiles)
+              .catch(console.error);
           }}
         >
           Compare
         </button>
+        <label style={{ marginLeft: 10 }}>
+          <input
+            type='checkbox'
+            checked={isWatching}
+            onChange={(e) => setIsWatching(e.target.checked)}
+          />{' '}
+          Watch for changes
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          <input
+            type='checkbox'
+            checked={normalizeText}
+            onChange={(e) => setNormalizeText(e.target.checked)}
+          />{' '}
+          Ignore line endings, trailing whitespace and encoding
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          <input
+            type='checkbox'
+            checked={compareStructuredData}
+            onChange={(e) => setCompareStructuredData(e.target.checked)}
+          />{' '}
+          Compare JSON, YAML and TOML as data
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          <input
+            type='checkbox'
+            checked={ignoreImageMetadata}
+            onChange={(e) => setIgnoreImageMetadata(e.target.checked)}
+          />{' '}
+          Detect images which only differ in metadata
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          Strategy:{' '}
+          <select
+            value={strategy}
+            onChange={(e) => setStrategy(e.target.value as CompareStrategy)}
+          >
+            <option value='InMemory'>Default</option>
+            <option value='MergeWalk'>Low memory (for huge directories)</option>
+            <option value='Merkle'>Directory hashes (skips identical subtrees)</option>
+          </select>
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          Ignore (comma separated globs, profiles only):{' '}
+          <input value={ignorePatterns} onChange={(e) => setIgnorePatterns(e.target.value)} />
+        </label>
       </div>
 
       <ComparisonTable tableApi={tableApi} />
@@ -45,7 +276,7 @@ const CompareScreen = () => {
             size='large'
             icon={<DoubleRightOutlined />}
             style={{ marginRight: 10 }}
-            onClick={() => modalApi.openModal(pathA, pathB, tableApi.selectedRows)}
+            onClick={() => modalApi.openModal(pathA, pathB, tableApi.selectedPaths)}
           >
             Copy A to B...
           </Button>
@@ -53,13 +284,24 @@ const CompareScreen = () => {
             type='primary'
             size='large'
             icon={<DoubleLeftOutlined />}
-            onClick={() => modalApi.openModal(pathB, pathA, tableApi.selectedRows)}
+            onClick={() => modalApi.openModal(pathB, pathA, tableApi.selectedPaths)}
           >
             Copy B to A...
           </Button>
         </div>
       ) : null}
 
+      {resolutions.length ? (
+        <>
+          <h3>Planned resolutions</h3>
+          {resolutions.map((r) => (
+            <div key={displayPath(r.path)}>
+              {displayPath(r.path)}: {r.action} ({r.reason})
+            </div>
+          ))}
+        </>
+      ) : null}
+
       <CopyModal modalApi={modalApi} />
     </>
   );
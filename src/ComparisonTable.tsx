@@ -2,7 +2,7 @@ import { useState } from 'react';
 import { Table, Alert } from 'antd';
 import { ColumnsType } from 'antd/es/table';
 
-import { Reason, CompareResult } from './types';
+import { Reason, CompareResult, EntryPath, displayPath, pathKey } from './types';
 
 type TableData = {
   key: string;
@@ -17,12 +17,24 @@ const reasonToType = (record: TableData) => {
       return 'error';
     case 'CouldNotReadDirectory':
       return 'error';
+    case 'CouldNotGetLastModified':
+      return 'error';
+    case 'CouldNotGetSize':
+      return 'error';
     case 'MissingInDirA':
       return 'error';
     case 'MissingInDirB':
       return 'error';
     case 'DifferingContent':
       return 'warning';
+    case 'EquivalentContent':
+      return 'info';
+    case 'SemanticallyEqual':
+      return 'info';
+    case 'DifferingData':
+      return 'warning';
+    case 'MetadataOnlyDifference':
+      return 'info';
     case 'TypeMismatch':
       return 'warning';
     default: {
@@ -39,14 +51,34 @@ export const useTableState = () => {
   const setComparisonResultPublic = (comparisonResult: CompareResult) => {
     setComparisonResult(comparisonResult);
     setSelectedRowKeys(
-      comparisonResult.filter((r) => (isAutoFixable(r.type) ? r.path : '')).map((r) => r.path)
+      comparisonResult.filter((r) => isAutoFixable(r.type)).map((r) => pathKey(r.path))
+    );
+  };
+
+  // Replace all results at or below `path` with `results`, see CompareUpdate in watch.rs
+  const updateComparisonResult = (path: EntryPath, results: CompareResult) => {
+    const key = pathKey(path);
+    const isAffected = (k: string) => key === '' || k === key || k.startsWith(key + '/');
+    setComparisonResult((previous) =>
+      previous
+        .filter((r) => !isAffected(pathKey(r.path)))
+        .concat(results)
+        .sort((a, b) => (pathKey(a.path) < pathKey(b.path) ? -1 : 1))
     );
+    setSelectedRowKeys((previous) => previous.filter((k) => !isAffected(k)));
   };
 
+  // The paths of the selected rows, as expected by the backend
+  const selectedPaths = comparisonResult
+    .filter((r) => selectedRowKeys.includes(pathKey(r.path)))
+    .map((r) => r.path);
+
   return {
     comparisonResult,
     setComparisonResult: setComparisonResultPublic,
+    updateComparisonResult,
     selectedRows: selectedRowKeys,
+    selectedPaths,
     setSelectedRowKeys, // TODO: Check for isAUtoFixable
   };
 };
@@ -102,47 +134,85 @@ const ComparisonTable = ({ tableApi }: Props) => {
         const { type } = res;
         switch (type) {
           case 'CouldNotReadDirectory':
-          case 'CouldNotCalculateHash': {
+          case 'CouldNotCalculateHash':
+          case 'CouldNotGetLastModified':
+          case 'CouldNotGetSize': {
             return {
-              key: res.path,
-              path: res.path,
+              key: pathKey(res.path),
+              path: displayPath(res.path),
               type: res.type,
               dirA: res.message,
             };
           }
           case 'MissingInDirA': {
             return {
-              key: res.path,
-              path: res.path,
+              key: pathKey(res.path),
+              path: displayPath(res.path),
               type: res.type,
               dirA: 'Missing',
             };
           }
           case 'MissingInDirB': {
             return {
-              key: res.path,
-              path: res.path,
+              key: pathKey(res.path),
+              path: displayPath(res.path),
               type: res.type,
               dirB: 'Missing',
             };
           }
           case 'DifferingContent': {
             return {
-              key: res.path,
-              path: res.path,
+              key: pathKey(res.path),
+              path: displayPath(res.path),
               type: res.type,
               dirA: `Differing content (${
                 res.last_modified_in_dir_a > res.last_modified_in_dir_b ? 'newer' : 'older'
-              })`,
+              }, ${res.size_in_dir_a} bytes, ${res.hash_in_dir_a.slice(0, 8)})`,
               dirB: `Differing content (${
                 res.last_modified_in_dir_a < res.last_modified_in_dir_b ? 'newer' : 'older'
-              })`,
+              }, ${res.size_in_dir_b} bytes (${res.size_delta >= 0 ? '+' : ''}${
+                res.size_delta
+              }), ${res.hash_in_dir_b.slice(0, 8)})`,
+            };
+          }
+          case 'EquivalentContent': {
+            const text = `Equivalent content (ignoring ${res.normalizers.join(', ')})`;
+            return {
+              key: pathKey(res.path),
+              path: displayPath(res.path),
+              type: res.type,
+              dirA: text,
+              dirB: text,
+            };
+          }
+          case 'SemanticallyEqual':
+          case 'DifferingData': {
+            const text =
+              res.type === 'SemanticallyEqual'
+                ? `Semantically equal ${res.format}`
+                : `Differing ${res.format} data: ${res.differing_keys.join(', ')}`;
+            return {
+              key: pathKey(res.path),
+              path: displayPath(res.path),
+              type: res.type,
+              dirA: text,
+              dirB: text,
+            };
+          }
+          case 'MetadataOnlyDifference': {
+            const text = `Only metadata differs (${res.differing_segments.join(', ')})`;
+            return {
+              key: pathKey(res.path),
+              path: displayPath(res.path),
+              type: res.type,
+              dirA: text,
+              dirB: text,
             };
           }
           case 'TypeMismatch': {
             return {
-              key: res.path,
-              path: res.path,
+              key: pathKey(res.path),
+              path: displayPath(res.path),
               type: res.type,
               dirA: res.type_in_dir_a,
               dirB: res.type_in_dir_b,
@@ -1,16 +1,19 @@
 import { useState } from 'react';
-import { Alert, Modal } from 'antd';
+import { Alert, Checkbox, Modal } from 'antd';
 import { invoke } from '@tauri-apps/api/tauri';
+import { EntryPath, displayPath, pathKey } from './types';
 
 type ErrorInfo = {
   message: string;
-  path: string;
+  path: EntryPath;
 };
 
 type ModalState = {
   sourcePath: string;
   targetPath: string;
-  selectedEntries: string[];
+  selectedEntries: EntryPath[];
+  verify: boolean;
+  preserveAttributes: boolean;
   copyErrors: ErrorInfo[];
 };
 
@@ -27,11 +30,13 @@ const Path = ({ children }: { children: React.ReactNode }) => (
 export const useModalState = () => {
   const [state, setState] = useState<ModalState | void>();
 
-  const openModal = (sourcePath: string, targetPath: string, selectedEntries: string[]) =>
+  const openModal = (sourcePath: string, targetPath: string, selectedEntries: EntryPath[]) =>
     setState({
       sourcePath,
       targetPath,
       selectedEntries,
+      verify: false,
+      preserveAttributes: false,
       copyErrors: [],
     });
 
@@ -42,7 +47,17 @@ export const useModalState = () => {
     setState({ ...state, copyErrors });
   };
 
-  return { openModal, closeModal, state, setCopyErrors };
+  const setVerify = (verify: boolean) => {
+    if (!state) return;
+    setState({ ...state, verify });
+  };
+
+  const setPreserveAttributes = (preserveAttributes: boolean) => {
+    if (!state) return;
+    setState({ ...state, preserveAttributes });
+  };
+
+  return { openModal, closeModal, state, setCopyErrors, setVerify, setPreserveAttributes };
 };
 
 type Props = {
@@ -53,10 +68,21 @@ const CopyModal = ({ modalApi }: Props) => {
   if (!modalApi.state) {
     return null;
   }
-  const { sourcePath, targetPath, selectedEntries, copyErrors } = modalApi.state;
+  const { sourcePath, targetPath, selectedEntries, verify, preserveAttributes, copyErrors } =
+    modalApi.state;
 
   const onOk = () =>
-    invoke<ErrorInfo[]>('copy', { sourcePath, targetPath, subPaths: selectedEntries })
+    invoke<ErrorInfo[]>('copy', {
+      sourcePath,
+      targetPath,
+      subPaths: selectedEntries,
+      options: {
+        verify,
+        preserve_timestamps: preserveAttributes,
+        preserve_ownership: preserveAttributes,
+        preserve_xattrs: preserveAttributes,
+      },
+    })
       .then((errors) => {
         if (errors.length) {
           modalApi.setCopyErrors(errors);
@@ -75,8 +101,17 @@ const CopyModal = ({ modalApi }: Props) => {
       <br />
       Files
       {selectedEntries.map((e) => (
-        <Path>{e}</Path>
+        <Path key={pathKey(e)}>{displayPath(e)}</Path>
       ))}
+      <Checkbox checked={verify} onChange={(e) => modalApi.setVerify(e.target.checked)}>
+        Verify copied files
+      </Checkbox>
+      <Checkbox
+        checked={preserveAttributes}
+        onChange={(e) => modalApi.setPreserveAttributes(e.target.checked)}
+      >
+        Preserve timestamps, ownership and extended attributes
+      </Checkbox>
       {copyErrors.length ? (
         <Alert
           type='error'
@@ -85,7 +120,7 @@ const CopyModal = ({ modalApi }: Props) => {
               While copying / overriding the following errors occured:
               {copyErrors.map((e) => (
                 <p>
-                  {e.path ? e.path + ' : ' : null} {e.message}
+                  {e.path ? displayPath(e.path) + ' : ' : null} {e.message}
                 </p>
               ))}
             </>
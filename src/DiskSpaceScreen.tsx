@@ -2,49 +2,135 @@ import { useEffect, useState } from 'react';
 import { Table } from 'antd';
 import { invoke } from '@tauri-apps/api/tauri';
 import { listen } from '@tauri-apps/api/event';
+import { open, save } from '@tauri-apps/api/dialog';
 import filesize from 'filesize';
 import DirectorySelect from './DirectorySelect';
+import { EntryPath, displayPath, pathKey } from './types';
 
 type FileNode = {
   type: 'File';
-  path: string;
+  path: EntryPath;
   size: number;
+  allocated_size: number;
+  links: number;
+  already_counted: boolean;
 };
 
 type DirectoryNode = {
   type: 'Dir';
-  path: string;
+  path: EntryPath;
   size: number;
+  allocated_size: number;
+  shared_size: number;
   number_of_files: number;
   content: Node[];
+  truncated: boolean;
+  unreadable_entries: number;
+  mount_point: boolean;
 };
 
-type Node = FileNode | DirectoryNode;
+type LinkNode = {
+  type: 'Link';
+  path: EntryPath;
+  target: EntryPath;
+  size: number;
+  allocated_size: number;
+};
+
+type Node = FileNode | DirectoryNode | LinkNode;
+
+type SymlinkPolicy = 'Skip' | 'CountLink' | 'Follow';
+
+type ScanOptions = {
+  threads: number | null;
+  max_depth: number | null;
+  symlink_policy: SymlinkPolicy;
+  one_file_system: boolean;
+  exclude_patterns: string[];
+  skip_hidden: boolean;
+};
+
+type AnalyseResult = {
+  result: DirectoryNode;
+  duration: number;
+  options: ScanOptions;
+};
+
+type EntryDiff = {
+  path: EntryPath;
+  change: 'Added' | 'Removed' | 'Changed' | 'Unchanged';
+  size_before: number;
+  size_after: number;
+  size_delta: number;
+  number_of_files_before: number;
+  number_of_files_after: number;
+  number_of_files_delta: number;
+  content: EntryDiff[];
+};
+
+type ScanDiff = {
+  root: EntryDiff;
+  largest_growth: EntryDiff[];
+};
+
+const formatDelta = (delta: number) => (delta < 0 ? '-' : '+') + filesize(Math.abs(delta));
+
+// Ant's tree table expects `children`, leave it out for entries without changed content
+const convertDiff = (diff: EntryDiff): EntryDiff & { children?: EntryDiff[] } => ({
+  ...diff,
+  children: diff.content.length ? diff.content.map(convertDiff) : undefined,
+});
+
+const scanFilters = [{ name: 'Disk usage scan', extensions: ['gz'] }];
 
 type AntTreeNode = {
   name: string;
   size: number;
   sizeHuman: string;
+  allocatedSize: number;
+  allocatedSizeHuman: string;
+  hardlinks: string;
   numberOfFiles: string;
   numberOfFilesHuman: string;
   key: string;
+  path: EntryPath;
+  isDirectory: boolean;
   children: AntTreeNode[] | void;
 };
 
 const convertNode = (node: Node): AntTreeNode => {
   return {
-    name: node.path.split('/').pop() || '',
+    name:
+      (displayPath(node.path).split('/').pop() || '') +
+      (node.type === 'Dir' && node.truncated ? ' (maximum depth reached)' : '') +
+      (node.type === 'Dir' && node.unreadable_entries
+        ? ` (${node.unreadable_entries} entries could not be read)`
+        : '') +
+      (node.type === 'Dir' && node.mount_point ? ' (other file system, skipped)' : '') +
+      (node.type === 'Link' ? ` → ${displayPath(node.target)}` : ''),
     size: node.size,
     sizeHuman: filesize(node.size),
+    allocatedSize: node.allocated_size,
+    allocatedSizeHuman: filesize(node.allocated_size),
+    hardlinks:
+      node.type === 'Dir'
+        ? node.shared_size
+          ? `${filesize(node.shared_size)} shared`
+          : ''
+        : node.type === 'File' && node.links > 1
+        ? `${node.links} links${node.already_counted ? ', counted elsewhere' : ''}`
+        : '',
     numberOfFiles: node.type === 'Dir' ? String(node.number_of_files) : '',
     numberOfFilesHuman: node.type === 'Dir' ? node.number_of_files.toLocaleString() : '',
-    key: node.path,
-    children: node.type === 'Dir' ? node.content.map(convertNode) : undefined,
+    key: pathKey(node.path),
+    path: node.path,
+    isDirectory: node.type === 'Dir',
+    children: node.type === 'Dir' && !node.truncated ? node.content.map(convertNode) : undefined,
   };
 };
 
 const replaceNode = (parentNode: AntTreeNode, newNode: Node): AntTreeNode => {
-  if (parentNode.key === newNode.path) {
+  if (parentNode.key === pathKey(newNode.path)) {
     return convertNode(newNode);
   } else {
     return {
@@ -54,8 +140,94 @@ const replaceNode = (parentNode: AntTreeNode, newNode: Node): AntTreeNode => {
   }
 };
 
+const findNode = (nodes: AntTreeNode[] | void, key: string): AntTreeNode | void => {
+  for (const node of nodes || []) {
+    const found = node.key === key ? node : findNode(node.children, key);
+    if (found) {
+      return found;
+    }
+  }
+};
+
+type Delta = { size: number; allocatedSize: number; numberOfFiles: number };
+
+// Replace a rescanned node and add the change of its totals to all its ancestors
+const applyRescan = (node: AntTreeNode, newNode: Node, delta: Delta): AntTreeNode => {
+  const key = pathKey(newNode.path);
+  if (node.key === key) {
+    return convertNode(newNode);
+  }
+  if (!findNode(node.children, key)) {
+    return node;
+  }
+  const size = node.size + delta.size;
+  const allocatedSize = node.allocatedSize + delta.allocatedSize;
+  const numberOfFiles = parseInt(node.numberOfFiles) + delta.numberOfFiles;
+  return {
+    ...node,
+    size,
+    sizeHuman: filesize(size),
+    allocatedSize,
+    allocatedSizeHuman: filesize(allocatedSize),
+    numberOfFiles: String(numberOfFiles),
+    numberOfFilesHuman: numberOfFiles.toLocaleString(),
+    children: node.children?.map((child) => applyRescan(child, newNode, delta)),
+  };
+};
+
 const DiskSpaceScreen = () => {
   const [path, setPath] = useState('');
+  const [threads, setThreads] = useState(0);
+  const [maxDepth, setMaxDepth] = useState(0);
+  // Show and sort by the space used on disk instead of the apparent size
+  const [useAllocatedSize, setUseAllocatedSize] = useState(false);
+  const [oneFileSystem, setOneFileSystem] = useState(false);
+  const [excludePatterns, setExcludePatterns] = useState('');
+  const [skipHidden, setSkipHidden] = useState(false);
+  const [appliedOptions, setAppliedOptions] = useState<ScanOptions | void>();
+  const [scanDiff, setScanDiff] = useState<ScanDiff | void>();
+
+  const rescan = (record: AntTreeNode) =>
+    invoke<DirectoryNode>('rescan_subtree', { path: record.path, options: appliedOptions })
+      .then((newNode) => {
+        const delta = {
+          size: newNode.size - record.size,
+          allocatedSize: newNode.allocated_size - record.allocatedSize,
+          numberOfFiles: newNode.number_of_files - parseInt(record.numberOfFiles),
+        };
+        setResult((previousRes) => previousRes?.map((p) => applyRescan(p, newNode, delta)));
+        setProgress((previous) =>
+          previous
+            ? {
+                ...previous,
+                numberOfFiles: previous.numberOfFiles + delta.numberOfFiles,
+                totalSize: previous.totalSize + delta.size,
+                totalAllocatedSize:
+                  previous.totalAllocatedSize !== undefined
+                    ? previous.totalAllocatedSize + delta.allocatedSize
+                    : undefined,
+              }
+            : previous
+        );
+      })
+      .catch(console.error);
+
+  // Compare a saved scan with the current one, or two saved scans (the first one is the older one)
+  const diffWithSavedScan = (twoFiles: boolean) =>
+    open({ filters: scanFilters, multiple: twoFiles })
+      .then((files) => {
+        const selected = typeof files === 'string' ? [files] : files || [];
+        if (selected.length !== (twoFiles ? 2 : 1)) {
+          return;
+        }
+        // The backend uses the older scan as before, the selection order is not reliable
+        return invoke<ScanDiff>('diff_disk_usage', {
+          file: selected[0],
+          otherFile: twoFiles ? selected[1] : null,
+        }).then(setScanDiff);
+      })
+      .catch(console.error);
+  const [symlinkPolicy, setSymlinkPolicy] = useState<SymlinkPolicy>('CountLink');
   const [result, setResult] = useState<AntTreeNode[] | void>();
   const [durationBE, setDurationBE] = useState<number | void>();
   const [durationFE, setDurationFE] = useState<number | void>();
@@ -63,6 +235,8 @@ const DiskSpaceScreen = () => {
     path: string;
     numberOfFiles: number;
     totalSize: number;
+    // Only known once the scan is finished
+    totalAllocatedSize?: number;
   } | void>();
 
   useEffect(() => {
@@ -87,20 +261,94 @@ const DiskSpaceScreen = () => {
   return (
     <>
       <DirectorySelect value={path} onChange={setPath} buttonLabel='Select directory' />
+      <div style={{ marginBottom: 10 }}>
+        <label>
+          Threads (0 = one per CPU):{' '}
+          <input
+            type='number'
+            min={0}
+            value={threads}
+            onChange={(e) => setThreads(parseInt(e.target.value) || 0)}
+          />
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          Maximum depth (0 = default):{' '}
+          <input
+            type='number'
+            min={0}
+            value={maxDepth}
+            onChange={(e) => setMaxDepth(parseInt(e.target.value) || 0)}
+          />
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          Symbolic links:{' '}
+          <select
+            value={symlinkPolicy}
+            onChange={(e) => setSymlinkPolicy(e.target.value as SymlinkPolicy)}
+          >
+            <option value='Skip'>Skip</option>
+            <option value='CountLink'>Count the link itself</option>
+            <option value='Follow'>Follow</option>
+          </select>
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          <input
+            type='checkbox'
+            checked={useAllocatedSize}
+            onChange={(e) => setUseAllocatedSize(e.target.checked)}
+          />{' '}
+          Use size on disk
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          <input
+            type='checkbox'
+            checked={oneFileSystem}
+            onChange={(e) => setOneFileSystem(e.target.checked)}
+          />{' '}
+          Stay on one file system
+        </label>
+      </div>
+      <div style={{ marginBottom: 10 }}>
+        <label>
+          Exclude (comma separated globs):{' '}
+          <input value={excludePatterns} onChange={(e) => setExcludePatterns(e.target.value)} />
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          <input
+            type='checkbox'
+            checked={skipHidden}
+            onChange={(e) => setSkipHidden(e.target.checked)}
+          />{' '}
+          Skip hidden files
+        </label>
+      </div>
       <button
         onClick={() => {
           setDurationBE(undefined);
           setDurationFE(undefined);
           setResult(undefined);
           let start = Date.now();
-          invoke<{ result: DirectoryNode; duration: number }>('analyze_disk_usage', { path })
+          const options: ScanOptions = {
+            threads: threads || null,
+            max_depth: maxDepth || null,
+            symlink_policy: symlinkPolicy,
+            one_file_system: oneFileSystem,
+            exclude_patterns: excludePatterns
+              .split(',')
+              .map((p) => p.trim())
+              .filter(Boolean),
+            skip_hidden: skipHidden,
+          };
+          invoke<AnalyseResult>('analyze_disk_usage', { path, options })
             .then((res) => {
               setDurationBE(res.duration);
+              setAppliedOptions(res.options);
               setDurationFE(Date.now() - start);
               setProgress({
                 path: '',
                 numberOfFiles: res.result.number_of_files,
                 totalSize: res.result.size,
+                totalAllocatedSize: res.result.allocated_size,
               });
               return res.result.content.map(convertNode);
             })
@@ -111,15 +359,110 @@ const DiskSpaceScreen = () => {
         Analyze!
       </button>
       <button onClick={() => invoke('abort').catch(console.error)}>Abort</button>
+      <button
+        disabled={!result}
+        onClick={() =>
+          save({ filters: scanFilters })
+            .then((file) => (file ? invoke('save_disk_usage', { file }) : undefined))
+            .catch(console.error)
+        }
+      >
+        Save scan
+      </button>
+      <button
+        onClick={() =>
+          open({ filters: scanFilters })
+            .then((file) =>
+              file ? invoke<DirectoryNode>('load_disk_usage', { file: file as string }) : undefined
+            )
+            .then((root) => {
+              if (!root) {
+                return;
+              }
+              setDurationBE(undefined);
+              setDurationFE(undefined);
+              setAppliedOptions(undefined);
+              setProgress({
+                path: '',
+                numberOfFiles: root.number_of_files,
+                totalSize: root.size,
+                totalAllocatedSize: root.allocated_size,
+              });
+              setResult(root.content.map(convertNode));
+            })
+            .catch(console.error)
+        }
+      >
+        Load scan
+      </button>
+      <button disabled={!result} onClick={() => diffWithSavedScan(false)}>
+        Compare with saved scan
+      </button>
+      <button onClick={() => diffWithSavedScan(true)}>Compare two saved scans</button>
+      {scanDiff ? (
+        <>
+          <h3>Largest growth</h3>
+          <Table
+            pagination={false}
+            rowKey={(d) => pathKey(d.path)}
+            columns={[
+              { title: 'Path', key: 'path', render: (_, d) => displayPath(d.path) },
+              { title: 'Change', dataIndex: 'change' },
+              { title: 'Size', key: 'size', render: (_, d) => formatDelta(d.size_delta) },
+              { title: 'Files', dataIndex: 'number_of_files_delta' },
+            ]}
+            dataSource={scanDiff.largest_growth}
+          />
+          <h3>All changes</h3>
+          <Table
+            pagination={false}
+            rowKey={(d) => pathKey(d.path)}
+            columns={[
+              {
+                title: 'Name',
+                key: 'name',
+                render: (_, d) => displayPath(d.path).split('/').pop(),
+              },
+              { title: 'Change', dataIndex: 'change' },
+              {
+                title: 'Size',
+                key: 'size',
+                render: (_, d) => `${filesize(d.size_after)} (${formatDelta(d.size_delta)})`,
+                sorter: (a, b) => a.size_delta - b.size_delta,
+              },
+              {
+                title: '# files',
+                key: 'files',
+                render: (_, d) =>
+                  `${d.number_of_files_after.toLocaleString()} (${d.number_of_files_delta})`,
+              },
+            ]}
+            dataSource={scanDiff.root.content.map(convertDiff)}
+          />
+        </>
+      ) : null}
       {progress ? (
         <div>
           <div>Files: {progress.numberOfFiles.toLocaleString()}</div>
           <div>Size: {filesize(progress.totalSize)}</div>
+          {progress.totalAllocatedSize !== undefined ? (
+            <div>Size on disk: {filesize(progress.totalAllocatedSize)}</div>
+          ) : null}
           <div style={{ textOverflow: 'ellipsis', overflow: 'hidden', whiteSpace: 'nowrap' }}>
             {progress.path}
           </div>
         </div>
       ) : null}
+      {appliedOptions &&
+      (appliedOptions.exclude_patterns.length || appliedOptions.skip_hidden) ? (
+        <div>
+          Excluded:{' '}
+          {[
+            ...appliedOptions.exclude_patterns,
+            ...(appliedOptions.skip_hidden ? ['hidden files'] : []),
+          ].join(', ')}
+        </div>
+      ) : null}
       {durationBE ? <div>Duration BE: {durationBE}</div> : null}
       {durationFE ? <div>Duration FE: {durationFE}</div> : null}
       {result ? (
@@ -127,18 +470,31 @@ const DiskSpaceScreen = () => {
           pagination={false}
           columns={[
             { title: 'Name', dataIndex: 'name' },
-            { title: 'Size', dataIndex: 'sizeHuman', sorter: (a, b) => a.size - b.size },
+            useAllocatedSize
+              ? {
+                  title: 'Size on disk',
+                  dataIndex: 'allocatedSizeHuman',
+                  sorter: (a, b) => a.allocatedSize - b.allocatedSize,
+                }
+              : { title: 'Size', dataIndex: 'sizeHuman', sorter: (a, b) => a.size - b.size },
+            { title: 'Hardlinks', dataIndex: 'hardlinks' },
             {
               title: '# files',
               dataIndex: 'numberOfFilesHuman',
               sorter: (a, b) => parseInt(a.numberOfFiles) - parseInt(b.numberOfFiles),
             },
+            {
+              title: '',
+              key: 'rescan',
+              render: (_, record) =>
+                record.isDirectory ? <button onClick={() => rescan(record)}>Rescan</button> : null,
+            },
           ]}
           dataSource={result}
           expandable={{
             onExpand: (expanded, record) => {
               if (expanded) {
-                invoke<DirectoryNode | void>('load_nested_directory', { path: record.key })
+                invoke<DirectoryNode | void>('load_nested_directory', { path: record.path })
                   .then((newNode) => {
                     if (!newNode) {
                       return;
@@ -0,0 +1,220 @@
+import { useEffect, useState } from 'react';
+import { Table } from 'antd';
+import { invoke } from '@tauri-apps/api/tauri';
+import { listen } from '@tauri-apps/api/event';
+import filesize from 'filesize';
+import DirectorySelect from './DirectorySelect';
+import {
+  DedupeMethod,
+  DedupeResult,
+  DuplicateGroup,
+  DuplicateSummary,
+  ErrorInfo,
+  Replacement,
+  displayPath,
+} from './types';
+
+const DuplicatesScreen = () => {
+  const [path, setPath] = useState('');
+  const [paths, setPaths] = useState<string[]>([]);
+  const [ignorePatterns, setIgnorePatterns] = useState('');
+  const [minSize, setMinSize] = useState(1);
+  const [groups, setGroups] = useState<DuplicateGroup[]>([]);
+  const [summary, setSummary] = useState<DuplicateSummary | void>();
+  const [method, setMethod] = useState<DedupeMethod>('Hardlink');
+  const [replacements, setReplacements] = useState<Replacement[]>([]);
+  const [dedupeErrors, setDedupeErrors] = useState<ErrorInfo[]>([]);
+
+  const loadReplacements = () =>
+    invoke<Replacement[]>('list_replacements').then(setReplacements).catch(console.error);
+
+  // Keep the first file of the group and replace all others
+  const dedupeGroup = (group: DuplicateGroup) =>
+    invoke<DedupeResult>('dedupe', {
+      requests: [{ original: group.paths[0], duplicates: group.paths.slice(1), hash: group.hash }],
+      method,
+    })
+      .then((result) => {
+        setDedupeErrors(result.errors);
+        setGroups((previous) => previous.filter((g) => g.hash !== group.hash));
+      })
+      .then(loadReplacements)
+      .catch(console.error);
+
+  useEffect(() => {
+    loadReplacements();
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, []);
+
+  useEffect(() => {
+    const unlisten = listen<DuplicateGroup>('duplicate-group', (event) =>
+      setGroups((previous) => [...previous, event.payload])
+    );
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  return (
+    <>
+      <DirectorySelect value={path} onChange={setPath} buttonLabel='Select directory' />
+      <button
+        disabled={!path || paths.includes(path)}
+        onClick={() => {
+          setPaths([...paths, path]);
+          setPath('');
+        }}
+      >
+        Add directory
+      </button>
+      <ul>
+        {paths.map((p) => (
+          <li key={p}>
+            {p} <button onClick={() => setPaths(paths.filter((other) => other !== p))}>Remove</button>
+          </li>
+        ))}
+      </ul>
+
+      <div style={{ marginBottom: 10 }}>
+        <label>
+          Ignore (comma separated globs):{' '}
+          <input value={ignorePatterns} onChange={(e) => setIgnorePatterns(e.target.value)} />
+        </label>
+        <label style={{ marginLeft: 10 }}>
+          Minimum size (bytes):{' '}
+          <input
+            type='number'
+            value={minSize}
+            onChange={(e) => setMinSize(parseInt(e.target.value) || 0)}
+          />
+        </label>
+      </div>
+
+      <button
+        disabled={!paths.length}
+        onClick={() => {
+          setGroups([]);
+          setSummary(undefined);
+          invoke<DuplicateSummary>('find_duplicates', {
+            paths,
+            options: {
+              ignore_patterns: ignorePatterns
+                .split(',')
+                .map((p) => p.trim())
+                .filter(Boolean),
+              min_size: minSize,
+            },
+          })
+            .then(setSummary)
+            .catch(console.error);
+        }}
+      >
+        Find duplicates
+      </button>
+      <button onClick={() => invoke('abort').catch(console.error)}>Abort</button>
+
+      {summary ? (
+        <div>
+          <div>
+            {summary.number_of_groups.toLocaleString()} groups, {filesize(summary.wasted_bytes)}{' '}
+            wasted{summary.aborted ? ' (aborted)' : ''}
+          </div>
+          <div>Duration: {summary.duration}</div>
+          {summary.errors.map((error) => (
+            <div key={displayPath(error.path)}>
+              {displayPath(error.path)}: {error.message}
+            </div>
+          ))}
+        </div>
+      ) : null}
+
+      <div style={{ margin: '10px 0' }}>
+        <label>
+          Replace duplicates by:{' '}
+          <select value={method} onChange={(e) => setMethod(e.target.value as DedupeMethod)}>
+            <option value='Hardlink'>Hardlinks</option>
+            <option value='Reflink'>Reflinks (copy if not supported)</option>
+          </select>
+        </label>
+      </div>
+      {dedupeErrors.map((error) => (
+        <div key={displayPath(error.path)}>
+          {displayPath(error.path)}: {error.message}
+        </div>
+      ))}
+
+      <Table
+        pagination={false}
+        rowKey='hash'
+        columns={[
+          {
+            title: 'Files',
+            dataIndex: 'paths',
+            render: (paths: DuplicateGroup['paths']) => (
+              <>
+                {paths.map((p) => (
+                  <div key={displayPath(p)}>{displayPath(p)}</div>
+                ))}
+              </>
+            ),
+          },
+          {
+            title: 'Size',
+            dataIndex: 'size',
+            render: (size: number) => filesize(size),
+            sorter: (a, b) => a.size - b.size,
+          },
+          {
+            title: 'Wasted',
+            dataIndex: 'wasted_bytes',
+            render: (size: number) => filesize(size),
+            sorter: (a, b) => a.wasted_bytes - b.wasted_bytes,
+          },
+          {
+            title: '',
+            key: 'dedupe',
+            render: (_, group) => (
+              <button onClick={() => dedupeGroup(group)}>Keep first, replace others</button>
+            ),
+          },
+        ]}
+        dataSource={groups}
+      />
+
+      {replacements.length ? (
+        <>
+          <h3>Replaced files</h3>
+          <Table
+            pagination={false}
+            rowKey={(r) => `${displayPath(r.path)}-${r.replaced_at}`}
+            columns={[
+              { title: 'File', key: 'path', render: (_, r) => displayPath(r.path) },
+              { title: 'Linked to', key: 'original', render: (_, r) => displayPath(r.original) },
+              { title: 'Method', dataIndex: 'method' },
+              {
+                title: '',
+                key: 'undo',
+                render: (_, r) => (
+                  <button
+                    onClick={() =>
+                      invoke<ErrorInfo[]>('undo_replacements', { replacements: [r] })
+                        .then(setDedupeErrors)
+                        .then(loadReplacements)
+                        .catch(console.error)
+                    }
+                  >
+                    Undo
+                  </button>
+                ),
+              },
+            ]}
+            dataSource={replacements}
+          />
+        </>
+      ) : null}
+    </>
+  );
+};
+
+export default DuplicatesScreen;
@@ -1,33 +1,88 @@
+// Paths which are valid UTF-8 are sent as plain strings, all other paths as raw bytes together
+// with a lossy version for displaying them. See entry_path.rs.
+export type EntryPath = string | { display: string; bytes: number[] };
+
+export const displayPath = (path: EntryPath) => (typeof path === 'string' ? path : path.display);
+
+// Unique string for a path, to be used as a key in lists and tables
+export const pathKey = (path: EntryPath) =>
+  typeof path === 'string' ? path : `raw:${path.bytes.join(',')}`;
+
 export type EntryType = 'Directory' | 'File' | 'Link' | 'Unknown';
 
+export type Normalizer = 'LineEndings' | 'TrailingWhitespace' | 'Bom' | 'Encoding';
+
+export type DataFormat = 'Json' | 'Yaml' | 'Toml';
+
+export type CompareOptions = {
+  normalizers?: Normalizer[];
+  compare_structured_data?: boolean;
+  ignore_image_metadata?: boolean;
+  ignore_patterns?: string[];
+};
+
+export type CompareStrategy = 'InMemory' | 'MergeWalk' | 'Merkle';
+
 export type CompareResult = (
   | {
       type: 'CouldNotReadDirectory';
-      path: string;
+      path: EntryPath;
       message: string;
     }
   | {
       type: 'CouldNotCalculateHash';
-      path: string;
+      path: EntryPath;
+      message: string;
+    }
+  | {
+      type: 'CouldNotGetLastModified';
+      path: EntryPath;
+      message: string;
+    }
+  | {
+      type: 'CouldNotGetSize';
+      path: EntryPath;
       message: string;
     }
   | {
       type: 'MissingInDirA';
-      path: string;
+      path: EntryPath;
     }
   | {
       type: 'MissingInDirB';
-      path: string;
+      path: EntryPath;
     }
   | {
       type: 'DifferingContent';
-      path: string;
+      path: EntryPath;
       last_modified_in_dir_a: number;
       last_modified_in_dir_b: number;
+      size_in_dir_a: number;
+      size_in_dir_b: number;
+      size_delta: number;
+      hash_in_dir_a: string;
+      hash_in_dir_b: string;
+    }
+  | {
+      type: 'EquivalentContent';
+      path: EntryPath;
+      normalizers: Normalizer[];
+    }
+  | {
+      type: 'SemanticallyEqual' | 'DifferingData';
+      path: EntryPath;
+      format: DataFormat;
+      differing_keys: string[];
+    }
+  | {
+      type: 'MetadataOnlyDifference';
+      path: EntryPath;
+      format: 'Jpeg' | 'Png';
+      differing_segments: string[];
     }
   | {
       type: 'TypeMismatch';
-      path: string;
+      path: EntryPath;
       type_in_dir_a: EntryType;
       type_in_dir_b: EntryType;
     }
@@ -36,7 +91,101 @@ export type CompareResult = (
 export type Reason =
   | 'CouldNotReadDirectory'
   | 'CouldNotCalculateHash'
+  | 'CouldNotGetLastModified'
+  | 'CouldNotGetSize'
   | 'MissingInDirA'
   | 'MissingInDirB'
   | 'DifferingContent'
+  | 'EquivalentContent'
+  | 'SemanticallyEqual'
+  | 'DifferingData'
+  | 'MetadataOnlyDifference'
   | 'TypeMismatch';
+
+export type ErrorInfo = {
+  path: EntryPath;
+  message: string;
+};
+
+export type DuplicateGroup = {
+  hash: string;
+  size: number;
+  paths: EntryPath[];
+  wasted_bytes: number;
+};
+
+export type DuplicateSummary = {
+  number_of_groups: number;
+  wasted_bytes: number;
+  errors: ErrorInfo[];
+  aborted: boolean;
+  duration: number;
+};
+
+export type DedupeMethod = 'Hardlink' | 'Reflink' | 'Copy';
+
+export type Replacement = {
+  path: EntryPath;
+  original: EntryPath;
+  method: DedupeMethod;
+  hash: string;
+  size: number;
+  replaced_at: number;
+  metadata: SavedMetadata;
+};
+
+// Only passed back to the backend, see `SavedMetadata` in copy.rs
+export type SavedMetadata = {
+  mode: number | null;
+  readonly: boolean;
+  owner: [number, number] | null;
+  accessed: [number, number];
+  modified: [number, number];
+  xattrs: [{ Unix: number[] } | { Windows: number[] }, number[]][];
+};
+
+export type DedupeResult = {
+  replacements: Replacement[];
+  errors: ErrorInfo[];
+};
+
+export type ResolutionPolicy =
+  | 'NewerWins'
+  | 'LargerWins'
+  | 'AlwaysA'
+  | 'AlwaysB'
+  | 'KeepBoth'
+  | 'Skip';
+
+export type ResolutionRule = {
+  pattern: string;
+  policy: ResolutionPolicy;
+};
+
+export type Resolution = {
+  path: EntryPath;
+  action: 'CopyAToB' | 'CopyBToA' | 'KeepBoth' | 'Skip';
+  policy: ResolutionPolicy;
+  rule: ResolutionRule | null;
+  reason: string;
+};
+
+export type Profile = {
+  name: string;
+  path_a: string;
+  path_b: string;
+  strategy: CompareStrategy;
+  options: CompareOptions;
+  sync_rules: ResolutionRule[];
+  default_policy: ResolutionPolicy | null;
+};
+
+export type DirectoryPair = {
+  path_a: string;
+  path_b: string;
+};
+
+export type ProfileRun = {
+  results: CompareResult;
+  resolutions: Resolution[];
+};